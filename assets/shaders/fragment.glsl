@@ -1,11 +1,108 @@
 #version 330 core
 in vec2 TextureCoordinate;
+noperspective in vec2 AffineTextureCoordinate;
+in vec3 Light;
+in vec3 Sunlight;
+in vec3 WorldPosition;
+in vec4 LightSpacePosition;
+in float Distance;
 
 out vec4 FragColor;
 
 uniform sampler2D albedo;
+uniform bool affine;
+// Bits per colour channel, or zero to keep the full colour depth
+uniform int color_depth;
+uniform bool dithering;
+// Zero for no fog at all, one for linear fog and two for exponential fog
+uniform int fog_falloff;
+uniform vec3 fog_color;
+uniform float fog_start;
+uniform float fog_end;
+uniform float fog_density;
+// Zero for no shadows at all, one for a shadow map and two for blob shadows
+uniform int shadow_mode;
+uniform sampler2D shadow_map;
+// How many texels around are averaged in every direction to soften the edges of the shadows
+uniform int shadow_pcf_radius;
+
+// Must match the cap on the number of blob shadows in the renderer
+#define MAX_BLOB_SHADOWS 8
+// How dark the middle of a blob shadow is
+#define BLOB_SHADOW_STRENGTH 0.6
+// How far above or below the bottom of a body a surface may be and still be darkened by its blob
+#define BLOB_SHADOW_HEIGHT 0.25
+uniform int blob_count;
+// The centre of the bottom of every body casting a blob shadow, along with its radius
+uniform vec4 blob_shadow[MAX_BLOB_SHADOWS];
+
+const float bayer[16] = float[](
+     0.0,  8.0,  2.0, 10.0,
+    12.0,  4.0, 14.0,  6.0,
+     3.0, 11.0,  1.0,  9.0,
+    15.0,  7.0, 13.0,  5.0
+);
+
+// How much of the sunlight reaches the fragment, from zero in full shadow to one when fully lit
+float sunlight_visibility()
+{
+    vec3 position = LightSpacePosition.xyz / LightSpacePosition.w * 0.5 + 0.5;
+    if (position.z > 1.0) {
+        return 1.0;
+    }
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float lit = 0.0;
+    for (int x = -shadow_pcf_radius; x <= shadow_pcf_radius; x++) {
+        for (int y = -shadow_pcf_radius; y <= shadow_pcf_radius; y++) {
+            float depth = texture(shadow_map, position.xy + vec2(x, y) * texel).r;
+            lit += position.z <= depth ? 1.0 : 0.0;
+        }
+    }
+    float samples = float((2 * shadow_pcf_radius + 1) * (2 * shadow_pcf_radius + 1));
+    return lit / samples;
+}
+
+// How much of the light the blob shadows leave over, from one when outside of all of them
+float blob_shadow_visibility()
+{
+    float visibility = 1.0;
+    for (int i = 0; i < blob_count; i++) {
+        vec4 blob = blob_shadow[i];
+        if (abs(WorldPosition.y - blob.y) > BLOB_SHADOW_HEIGHT) {
+            continue;
+        }
+        float distance = length(WorldPosition.xz - blob.xz);
+        float shadow = 1.0 - smoothstep(blob.w * 0.5, blob.w, distance);
+        visibility = min(visibility, 1.0 - shadow * BLOB_SHADOW_STRENGTH);
+    }
+    return visibility;
+}
 
 void main()
 {
-    FragColor = texture(albedo, TextureCoordinate);
-}
\ No newline at end of file
+    vec4 color = texture(albedo, affine ? AffineTextureCoordinate : TextureCoordinate);
+    vec3 light = Light + Sunlight;
+    if (shadow_mode == 1) {
+        light = Light + Sunlight * sunlight_visibility();
+    } else if (shadow_mode == 2) {
+        light *= blob_shadow_visibility();
+    }
+    color.rgb = min(color.rgb * light, 1.0);
+    float fog = 0.0;
+    if (fog_falloff == 1) {
+        fog = clamp((Distance - fog_start) / max(fog_end - fog_start, 0.0001), 0.0, 1.0);
+    } else if (fog_falloff == 2) {
+        fog = 1.0 - exp(-fog_density * Distance);
+    }
+    color.rgb = mix(color.rgb, fog_color, fog);
+    if (color_depth > 0) {
+        float levels = exp2(float(color_depth)) - 1.0;
+        float threshold = 0.5;
+        if (dithering) {
+            ivec2 cell = ivec2(gl_FragCoord.xy) % 4;
+            threshold = (bayer[cell.y * 4 + cell.x] + 0.5) / 16.0;
+        }
+        color.rgb = floor(color.rgb * levels + threshold) / levels;
+    }
+    FragColor = color;
+}
@@ -0,0 +1,6 @@
+#version 330 core
+
+void main()
+{
+    // Only the depth is written, which happens on its own
+}
\ No newline at end of file
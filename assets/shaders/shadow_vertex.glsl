@@ -0,0 +1,10 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 3) in mat4 aModel;
+
+uniform mat4 light_space;
+
+void main()
+{
+    gl_Position = light_space * aModel * vec4(aPos, 1.0);
+}
\ No newline at end of file
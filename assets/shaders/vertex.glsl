@@ -1,15 +1,67 @@
 #version 330 core
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec2 aTexCoord;
+layout (location = 2) in vec3 aNormal;
+layout (location = 3) in mat4 aModel;
 
 out vec2 TextureCoordinate;
+noperspective out vec2 AffineTextureCoordinate;
+// The light reaching the vertex from everywhere but the sun, and from the sun on its own
+out vec3 Light;
+out vec3 Sunlight;
+out vec3 WorldPosition;
+out vec4 LightSpacePosition;
+// How far the vertex is from the camera
+out float Distance;
 
-uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
+// The size of the grid on screen which vertices are snapped to, or zero to leave them be
+uniform vec2 snap_resolution;
+// The direction the sunlight travels in, pointing away from the sun
+uniform vec3 sun_direction;
+uniform vec3 sun_color;
+uniform vec3 ambient;
+// Takes a point in the world into the shadow map
+uniform mat4 light_space;
+
+// Must match the cap on the number of lights in the renderer
+#define MAX_LIGHTS 8
+uniform int light_count;
+uniform vec3 light_position[MAX_LIGHTS];
+uniform vec3 light_color[MAX_LIGHTS];
+uniform float light_range[MAX_LIGHTS];
+// The direction of a spot light, or zero for a point light
+uniform vec3 light_direction[MAX_LIGHTS];
+// The cosines of the inner and outer angle of the cone of a spot light
+uniform vec2 light_cone[MAX_LIGHTS];
 
 void main()
 {
-    gl_Position = projection * view * model * vec4(aPos, 1.0);
+    vec4 view_position = view * aModel * vec4(aPos, 1.0);
+    gl_Position = projection * view_position;
+    Distance = length(view_position.xyz);
+    if (snap_resolution.x > 0.0 && snap_resolution.y > 0.0) {
+        vec2 half_resolution = snap_resolution / 2.0;
+        vec2 snapped = floor(gl_Position.xy / gl_Position.w * half_resolution + 0.5) / half_resolution;
+        gl_Position.xy = snapped * gl_Position.w;
+    }
     TextureCoordinate = aTexCoord;
-}
\ No newline at end of file
+    AffineTextureCoordinate = aTexCoord;
+
+    // Lit once per vertex and blended across the face, which is known as Gouraud shading
+    vec3 normal = normalize(transpose(inverse(mat3(aModel))) * aNormal);
+    Light = ambient;
+    Sunlight = sun_color * max(dot(normal, -sun_direction), 0.0);
+    vec3 world_position = vec3(aModel * vec4(aPos, 1.0));
+    WorldPosition = world_position;
+    LightSpacePosition = light_space * vec4(world_position, 1.0);
+    for (int i = 0; i < light_count; i++) {
+        vec3 to_light = light_position[i] - world_position;
+        float distance = length(to_light);
+        vec3 direction = to_light / max(distance, 0.0001);
+        float attenuation = clamp(1.0 - distance / light_range[i], 0.0, 1.0);
+        float spot = smoothstep(light_cone[i].y, light_cone[i].x, dot(-direction, light_direction[i]));
+        Light += light_color[i] * max(dot(normal, direction), 0.0) * attenuation * attenuation * spot;
+    }
+}
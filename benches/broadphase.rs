@@ -0,0 +1,116 @@
+//! Compares the time spent by the collision system per frame in a 100x100 cityscape between a
+//! broadphase with cells the size of a tile and a brute force baseline. The baseline puts the whole
+//! city into a single cell, which has the broadphase hand every static body to every dynamic body.
+//! Run with `cargo bench --bench broadphase`.
+use glam::Vec3;
+use std::time::{Duration, Instant};
+use xtra_cheez::core::ecs::component::Transform;
+use xtra_cheez::core::ecs::{ECSBuilder, ECS};
+use xtra_cheez::core::physics;
+use xtra_cheez::core::physics::broadphase::Broadphase;
+use xtra_cheez::core::physics::collider::Collider;
+use xtra_cheez::core::physics::material::PhysicsMaterial;
+use xtra_cheez::core::physics::{DynamicPhysicsBody, PhysicsBody, ALL_LAYERS, DEFAULT_LAYER};
+
+const CITY_SIZE: i32 = 100;
+const DYNAMIC_BODIES: i32 = 20;
+const FRAMES: u32 = 100;
+
+fn main() {
+    let mut ecs = build_world(f32::MAX);
+    let brute_force = measure(|| physics::collision_system(&mut ecs));
+    let mut ecs = build_world(8.0);
+    let broadphase = measure(|| physics::collision_system(&mut ecs));
+    println!(
+        "{} static and {} dynamic bodies",
+        CITY_SIZE * CITY_SIZE / 2,
+        DYNAMIC_BODIES
+    );
+    println!("brute force: {:?} per frame", brute_force);
+    println!("broadphase:  {:?} per frame", broadphase);
+    println!(
+        "speed-up:    {:.1}x",
+        brute_force.as_secs_f64() / broadphase.as_secs_f64()
+    );
+}
+
+fn measure(mut frame: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame();
+    }
+    start.elapsed() / FRAMES
+}
+
+fn build_world(cell_size: f32) -> ECS {
+    let mut ecs = ECSBuilder::new()
+        .with_component::<Transform>()
+        .with_component::<PhysicsBody>()
+        .with_component::<DynamicPhysicsBody>()
+        .with_resource(Broadphase::new(cell_size))
+        .build();
+    // Obstacles are placed on every other tile in the same way as the cityscape generator, the
+    // dynamic bodies are spread out along the roads in between them. A tile is a road when the
+    // sum of its coordinates is odd.
+    for x in 0..CITY_SIZE {
+        for y in 0..CITY_SIZE {
+            if (x + y) % 2 == 0 {
+                spawn(&mut ecs, (x, y), None);
+            }
+        }
+    }
+    for i in 0..DYNAMIC_BODIES {
+        let tile = (i * 10 % CITY_SIZE, (i * 10 / CITY_SIZE) * 2 + 1);
+        spawn(
+            &mut ecs,
+            tile,
+            Some(DynamicPhysicsBody {
+                force: Vec3::new(10.0, 0.0, 0.0),
+                velocity: Vec3::default(),
+                base: PhysicsBody {
+                    mass: 1.0,
+                    collider: Collider::rectangle(0.8, 2.0),
+                    layer: DEFAULT_LAYER,
+                    collides_with: ALL_LAYERS,
+                    material: PhysicsMaterial::default(),
+                    height: None,
+                },
+                torque: 0.0,
+                angular_velocity: 0.0,
+                inertia: 1.0,
+                grounded: true,
+                rest_time: 0.0,
+            }),
+        );
+    }
+    ecs
+}
+
+fn spawn(ecs: &mut ECS, tile: (i32, i32), body: Option<DynamicPhysicsBody>) {
+    let id = ecs.create_entity();
+    ecs.attach_component(
+        id,
+        Transform {
+            scale: Vec3::new(4.0, 4.0, 4.0),
+            position: Vec3::new(8.0 * tile.0 as f32, 0.0, 8.0 * tile.1 as f32),
+            rotation: Vec3::default(),
+        },
+    )
+    .unwrap();
+    match body {
+        Some(body) => ecs.attach_component(id, body).unwrap(),
+        None => physics::attach_static_body(
+            ecs,
+            id,
+            PhysicsBody {
+                mass: 50.0,
+                collider: Collider::rectangle(2.0, 2.0),
+                layer: DEFAULT_LAYER,
+                collides_with: ALL_LAYERS,
+                material: PhysicsMaterial::default(),
+                height: None,
+            },
+        )
+        .unwrap(),
+    }
+}
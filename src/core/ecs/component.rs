@@ -1,5 +1,5 @@
 use crate::core::radians;
-use crate::core::render::model::{Material, Mesh};
+use crate::core::render::model::{Bounds, Material, Mesh};
 use glam::Vec3;
 use sdl2::keyboard::Scancode;
 
@@ -43,6 +43,7 @@ pub struct KeyboardControls {
     pub backward: Scancode,
     pub left: Scancode,
     pub right: Scancode,
+    pub handbrake: Scancode,
 }
 
 impl Default for KeyboardControls {
@@ -52,14 +53,24 @@ impl Default for KeyboardControls {
             backward: Scancode::S,
             left: Scancode::A,
             right: Scancode::D,
+            handbrake: Scancode::Space,
         }
     }
 }
 
+/// The attribute location of the first column of the per-instance model matrix.
+pub const INSTANCE_MATRIX_LOCATION: u32 = 3;
+
+/// A mesh uploaded to the GPU. Cloning a model shares the buffers of the mesh rather than copying
+/// them, and every entity sharing the buffers is drawn in a single instanced draw call per
+/// material, with the model matrices of the entities streamed into the instance buffer.
 #[derive(Clone)]
 pub struct Model {
     pub materials: Vec<Material>,
     pub vao: u32,
+    pub instance_vbo: u32,
+    /// The bounds of the mesh, which decide whether the model is in view of the camera.
+    pub bounds: Bounds,
 }
 
 impl Model {
@@ -88,7 +99,7 @@ impl Model {
                 3,
                 gl::FLOAT,
                 gl::FALSE,
-                (5 * size_of::<f32>()) as gl::types::GLint,
+                (8 * size_of::<f32>()) as gl::types::GLint,
                 0 as *const _,
             );
             gl::EnableVertexAttribArray(0);
@@ -97,15 +108,47 @@ impl Model {
                 2,
                 gl::FLOAT,
                 gl::FALSE,
-                (5 * size_of::<f32>()) as gl::types::GLint,
+                (8 * size_of::<f32>()) as gl::types::GLint,
                 (3 * size_of::<f32>()) as *const gl::types::GLvoid,
             );
             gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(
+                2,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                (8 * size_of::<f32>()) as gl::types::GLint,
+                (5 * size_of::<f32>()) as *const gl::types::GLvoid,
+            );
+            gl::EnableVertexAttribArray(2);
+        }
+
+        // The model matrix of every instance takes up four attributes, one for each column, which
+        // advance once per instance rather than once per vertex.
+        let mut instance_vbo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut instance_vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
+            for column in 0..4 {
+                let location = INSTANCE_MATRIX_LOCATION + column;
+                gl::VertexAttribPointer(
+                    location,
+                    4,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    (16 * size_of::<f32>()) as gl::types::GLint,
+                    (column as usize * 4 * size_of::<f32>()) as *const gl::types::GLvoid,
+                );
+                gl::EnableVertexAttribArray(location);
+                gl::VertexAttribDivisor(location, 1);
+            }
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
             gl::BindVertexArray(0);
         }
         Self {
             vao,
+            instance_vbo,
+            bounds: *mesh.bounds(),
             materials: mesh.materials,
         }
     }
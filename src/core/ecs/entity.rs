@@ -1,13 +1,16 @@
 use crate::core::ecs::EcsError;
 use std::any::{Any, TypeId};
 use std::cell::RefCell;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, VecDeque};
 use std::rc::Rc;
 
+/// Stores the components of every entity, with a column per entity in the table of each component
+/// type. The component types are kept in a stable order, so that walking through them does the
+/// same thing on every run.
 #[derive(Default)]
 pub struct EntityRegistry {
     free_slots: VecDeque<usize>,
-    components: HashMap<TypeId, Vec<Option<Rc<RefCell<Box<dyn Any>>>>>>,
+    components: BTreeMap<TypeId, Vec<Option<Rc<RefCell<Box<dyn Any>>>>>>,
 }
 
 impl EntityRegistry {
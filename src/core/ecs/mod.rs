@@ -98,6 +98,11 @@ impl ECS {
         self.entity_registry.create_entity()
     }
 
+    /// Drops every component of the entity, leaving its slot free for the next entity created.
+    pub fn free_entity(&mut self, id: EntityID) {
+        self.entity_registry.free_entity(id)
+    }
+
     pub fn attach_component(&mut self, id: EntityID, component: impl Any) -> Result<(), EcsError> {
         self.entity_registry.set_entity_component(id, component)
     }
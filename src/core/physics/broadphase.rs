@@ -0,0 +1,232 @@
+use crate::core::ecs::EntityID;
+use glam::Vec2;
+use std::collections::HashMap;
+
+/// An axis-aligned rectangle on the XZ-plane.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Aabb {
+    pub fn from_points(points: &[Vec2]) -> Self {
+        let mut aabb = Self {
+            min: Vec2::splat(f32::INFINITY),
+            max: Vec2::splat(f32::NEG_INFINITY),
+        };
+        for point in points {
+            aabb.min = aabb.min.min(*point);
+            aabb.max = aabb.max.max(*point);
+        }
+        aabb
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+    }
+}
+
+type Cell = (i32, i32);
+
+struct GridEntry {
+    bounds: Aabb,
+    first: Cell,
+    last: Cell,
+}
+
+/// A uniform grid bucketing entities by the cells their bounds touch. Finding the entities close
+/// to some bounds then only requires looking through the few cells those bounds touch rather than
+/// through every entity in the world.
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<EntityID>>,
+    entries: HashMap<EntityID, GridEntry>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn contains(&self, id: EntityID) -> bool {
+        self.entries.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = EntityID> + '_ {
+        self.entries.keys().copied()
+    }
+
+    /// Inserts an entity into the grid or moves it if it is already tracked. Moving an entity is
+    /// cheap as long as it stays within the same cells, which it does for most frames.
+    pub fn update(&mut self, id: EntityID, bounds: Aabb) {
+        let (first, last) = (self.cell(bounds.min), self.cell(bounds.max));
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.bounds = bounds;
+            if entry.first == first && entry.last == last {
+                return;
+            }
+        }
+        self.remove(id);
+        for x in first.0..=last.0 {
+            for y in first.1..=last.1 {
+                self.cells.entry((x, y)).or_default().push(id);
+            }
+        }
+        self.entries.insert(
+            id,
+            GridEntry {
+                bounds,
+                first,
+                last,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: EntityID) {
+        let Some(entry) = self.entries.remove(&id) else {
+            return;
+        };
+        for x in entry.first.0..=entry.last.0 {
+            for y in entry.first.1..=entry.last.1 {
+                if let Some(cell) = self.cells.get_mut(&(x, y)) {
+                    cell.retain(|other| *other != id);
+                    if cell.is_empty() {
+                        self.cells.remove(&(x, y));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Finds every entity with bounds intersecting `bounds`, ordered by entity ID.
+    pub fn query(&self, bounds: &Aabb) -> Vec<EntityID> {
+        let (first, last) = (self.cell(bounds.min), self.cell(bounds.max));
+        let mut found = vec![];
+        for x in first.0..=last.0 {
+            for y in first.1..=last.1 {
+                let Some(cell) = self.cells.get(&(x, y)) else {
+                    continue;
+                };
+                for id in cell {
+                    if self.entries[id].bounds.intersects(bounds) {
+                        found.push(*id);
+                    }
+                }
+            }
+        }
+        // Entities spanning several cells are found once for every cell they share with the
+        // queried bounds.
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    fn cell(&self, point: Vec2) -> Cell {
+        (
+            (point.x / self.cell_size).floor() as i32,
+            (point.y / self.cell_size).floor() as i32,
+        )
+    }
+}
+
+/// Keeps track of the physics bodies in the world, so that the narrowphase only has to consider
+/// pairs of bodies which are close to each other. The world space polygons of every body are
+/// cached alongside the grids to avoid rebuilding them for every pair.
+pub struct Broadphase {
+    pub statics: SpatialGrid,
+    pub dynamics: SpatialGrid,
+    pub kinematics: SpatialGrid,
+    polygons: HashMap<EntityID, Vec<Vec<Vec2>>>,
+}
+
+impl Broadphase {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            statics: SpatialGrid::new(cell_size),
+            dynamics: SpatialGrid::new(cell_size),
+            kinematics: SpatialGrid::new(cell_size),
+            polygons: HashMap::new(),
+        }
+    }
+
+    pub fn polygons(&self, id: EntityID) -> &[Vec<Vec2>] {
+        &self.polygons[&id]
+    }
+
+    pub fn update_static(&mut self, id: EntityID, polygons: Vec<Vec<Vec2>>) {
+        self.statics
+            .update(id, Aabb::from_points(&polygons.concat()));
+        self.polygons.insert(id, polygons);
+    }
+
+    pub fn update_dynamic(&mut self, id: EntityID, polygons: Vec<Vec<Vec2>>) {
+        self.dynamics
+            .update(id, Aabb::from_points(&polygons.concat()));
+        self.polygons.insert(id, polygons);
+    }
+
+    pub fn update_kinematic(&mut self, id: EntityID, polygons: Vec<Vec<Vec2>>) {
+        self.kinematics
+            .update(id, Aabb::from_points(&polygons.concat()));
+        self.polygons.insert(id, polygons);
+    }
+
+    pub fn remove(&mut self, id: EntityID) {
+        self.statics.remove(id);
+        self.dynamics.remove(id);
+        self.kinematics.remove(id);
+        self.polygons.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabb(min: (f32, f32), max: (f32, f32)) -> Aabb {
+        Aabb {
+            min: Vec2::new(min.0, min.1),
+            max: Vec2::new(max.0, max.1),
+        }
+    }
+
+    #[test]
+    fn spatial_grid_query() {
+        let mut grid = SpatialGrid::new(8.0);
+        grid.update(0, aabb((0.0, 0.0), (4.0, 4.0)));
+        grid.update(1, aabb((6.0, 6.0), (20.0, 20.0)));
+        grid.update(2, aabb((40.0, 40.0), (44.0, 44.0)));
+        assert_eq!(grid.query(&aabb((3.0, 3.0), (7.0, 7.0))), vec![0, 1]);
+        assert_eq!(grid.query(&aabb((10.0, 10.0), (12.0, 12.0))), vec![1]);
+        assert!(grid.query(&aabb((30.0, 0.0), (32.0, 2.0))).is_empty());
+    }
+
+    #[test]
+    fn spatial_grid_update_moves_entity() {
+        let mut grid = SpatialGrid::new(8.0);
+        grid.update(0, aabb((0.0, 0.0), (4.0, 4.0)));
+        grid.update(0, aabb((40.0, 40.0), (44.0, 44.0)));
+        assert!(grid.query(&aabb((0.0, 0.0), (4.0, 4.0))).is_empty());
+        assert_eq!(grid.query(&aabb((40.0, 40.0), (41.0, 41.0))), vec![0]);
+
+        grid.remove(0);
+        assert!(grid.is_empty());
+        assert!(grid.query(&aabb((40.0, 40.0), (41.0, 41.0))).is_empty());
+    }
+}
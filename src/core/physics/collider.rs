@@ -0,0 +1,347 @@
+use crate::core::ecs::component::Transform;
+use crate::core::physics::flatten_vec3;
+use crate::core::radians;
+use crate::core::render::model::{Bounds, Mesh};
+use glam::{Mat4, Vec2, Vec3};
+use std::f32::consts::{PI, TAU};
+
+/// How many edges make up the polygon standing in for a full circle, half of them are used for
+/// each end of a capsule.
+const CIRCLE_SEGMENTS: usize = 16;
+
+/// The footprint of a physics body on the XZ-plane. Shapes are sized in the space of the body, the
+/// transform of the body then scales, rotates and moves them into the world. Offsets move a shape
+/// away from the centre of the body and rotations (in degrees) turn it around its own centre.
+///
+/// Round shapes are approximated by convex polygons, which lets every shape go through the same
+/// separating axis tests as the boxes do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Collider {
+    Box {
+        width: f32,
+        depth: f32,
+        offset: Vec2,
+        rotation: f32,
+    },
+    Circle {
+        radius: f32,
+        offset: Vec2,
+    },
+    /// A box with rounded ends, where `length` is the distance between the centres of the two
+    /// ends along the Z-axis.
+    Capsule {
+        radius: f32,
+        length: f32,
+        offset: Vec2,
+        rotation: f32,
+    },
+    /// A convex polygon given by its corners in the space of the body.
+    Polygon(Vec<Vec2>),
+    /// Several shapes acting as one, each of which has to be convex while the whole does not.
+    Compound(Vec<Collider>),
+}
+
+impl Collider {
+    pub fn rectangle(width: f32, depth: f32) -> Self {
+        Collider::Box {
+            width,
+            depth,
+            offset: Vec2::ZERO,
+            rotation: 0.0,
+        }
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Collider::Circle {
+            radius,
+            offset: Vec2::ZERO,
+        }
+    }
+
+    pub fn capsule(radius: f32, length: f32) -> Self {
+        Collider::Capsule {
+            radius,
+            length,
+            offset: Vec2::ZERO,
+            rotation: 0.0,
+        }
+    }
+
+    /// A box covering the footprint of `bounds`.
+    pub fn from_bounds(bounds: &Bounds) -> Self {
+        let size = bounds.size();
+        let center = bounds.center();
+        Collider::rectangle(size.x, size.z).with_offset(Vec2::new(center.x, center.z))
+    }
+
+    /// A box fitted to the footprint of a mesh, meant for bodies using the mesh as their model.
+    pub fn fit_box(mesh: &Mesh) -> Self {
+        Collider::from_bounds(mesh.bounds())
+    }
+
+    /// A capsule fitted to the footprint of a mesh, lying along the longer side of its bounds.
+    pub fn fit_capsule(mesh: &Mesh) -> Self {
+        let bounds = mesh.bounds();
+        let (size, center) = (bounds.size(), bounds.center());
+        let radius = size.x.min(size.z) / 2.0;
+        let capsule = Collider::capsule(radius, size.x.max(size.z) - radius * 2.0);
+        let capsule = if size.x > size.z {
+            capsule.with_rotation(90.0)
+        } else {
+            capsule
+        };
+        capsule.with_offset(Vec2::new(center.x, center.z))
+    }
+
+    /// The convex hull of the footprint of a mesh, which hugs the mesh tighter than a box does.
+    pub fn fit_hull(mesh: &Mesh) -> Self {
+        if mesh.footprint().len() < 3 {
+            return Collider::fit_box(mesh);
+        }
+        Collider::Polygon(mesh.footprint().to_vec())
+    }
+
+    /// Moves the shape by `by`, where the Y-component of the offset is along the Z-axis.
+    pub fn with_offset(self, by: Vec2) -> Self {
+        match self {
+            Collider::Box {
+                width,
+                depth,
+                offset,
+                rotation,
+            } => Collider::Box {
+                width,
+                depth,
+                offset: offset + by,
+                rotation,
+            },
+            Collider::Circle { radius, offset } => Collider::Circle {
+                radius,
+                offset: offset + by,
+            },
+            Collider::Capsule {
+                radius,
+                length,
+                offset,
+                rotation,
+            } => Collider::Capsule {
+                radius,
+                length,
+                offset: offset + by,
+                rotation,
+            },
+            Collider::Polygon(points) => {
+                Collider::Polygon(points.into_iter().map(|point| point + by).collect())
+            }
+            Collider::Compound(shapes) => Collider::Compound(
+                shapes
+                    .into_iter()
+                    .map(|shape| shape.with_offset(by))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Turns the shape around its own centre, or a polygon or compound collider around the centre
+    /// of the body.
+    pub fn with_rotation(self, degrees: f32) -> Self {
+        match self {
+            Collider::Box {
+                width,
+                depth,
+                offset,
+                rotation,
+            } => Collider::Box {
+                width,
+                depth,
+                offset,
+                rotation: rotation + degrees,
+            },
+            Collider::Capsule {
+                radius,
+                length,
+                offset,
+                rotation,
+            } => Collider::Capsule {
+                radius,
+                length,
+                offset,
+                rotation: rotation + degrees,
+            },
+            Collider::Circle { .. } => self,
+            Collider::Polygon(points) => Collider::Polygon(
+                points
+                    .into_iter()
+                    .map(|point| rotate(point, degrees))
+                    .collect(),
+            ),
+            Collider::Compound(shapes) => Collider::Compound(
+                shapes
+                    .into_iter()
+                    .map(|shape| {
+                        let offset = shape.offset();
+                        shape
+                            .with_offset(rotate(offset, degrees) - offset)
+                            .with_rotation(degrees)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn offset(&self) -> Vec2 {
+        match self {
+            Collider::Box { offset, .. }
+            | Collider::Circle { offset, .. }
+            | Collider::Capsule { offset, .. } => *offset,
+            Collider::Polygon(_) | Collider::Compound(_) => Vec2::ZERO,
+        }
+    }
+
+    /// The convex polygons making up the collider once placed in the world by `transform`.
+    pub fn polygons(&self, transform: &Transform) -> Vec<Vec<Vec2>> {
+        let matrix = Mat4::from_translation(transform.position)
+            * Mat4::from_scale(transform.scale)
+            * Mat4::from_rotation_y(radians(transform.rotation.y));
+        let mut polygons = vec![];
+        self.local_polygons(&mut polygons);
+        for polygon in polygons.iter_mut() {
+            for point in polygon.iter_mut() {
+                *point = flatten_vec3(matrix.transform_point3(Vec3::new(point.x, 0.0, point.y)));
+            }
+        }
+        polygons
+    }
+
+    fn local_polygons(&self, polygons: &mut Vec<Vec<Vec2>>) {
+        match self {
+            Collider::Box {
+                width,
+                depth,
+                offset,
+                rotation,
+            } => {
+                let half = Vec2::new(width / 2.0, depth / 2.0);
+                polygons.push(
+                    [
+                        Vec2::new(half.x, half.y),
+                        Vec2::new(half.x, -half.y),
+                        Vec2::new(-half.x, -half.y),
+                        Vec2::new(-half.x, half.y),
+                    ]
+                    .iter()
+                    .map(|corner| rotate(*corner, *rotation) + *offset)
+                    .collect(),
+                );
+            }
+            Collider::Circle { radius, offset } => {
+                polygons.push(
+                    (0..CIRCLE_SEGMENTS)
+                        .map(|i| arc_point(*radius, TAU * i as f32 / CIRCLE_SEGMENTS as f32))
+                        .map(|point| point + *offset)
+                        .collect(),
+                );
+            }
+            Collider::Capsule {
+                radius,
+                length,
+                offset,
+                rotation,
+            } => {
+                let half_segments = CIRCLE_SEGMENTS / 2;
+                let end = Vec2::new(0.0, length / 2.0);
+                let front = (0..=half_segments)
+                    .map(|i| end + arc_point(*radius, PI * i as f32 / half_segments as f32));
+                let back = (0..=half_segments)
+                    .map(|i| -end + arc_point(*radius, PI + PI * i as f32 / half_segments as f32));
+                polygons.push(
+                    front
+                        .chain(back)
+                        .map(|point| rotate(point, *rotation) + *offset)
+                        .collect(),
+                );
+            }
+            Collider::Polygon(points) => polygons.push(points.clone()),
+            Collider::Compound(shapes) => {
+                for shape in shapes {
+                    shape.local_polygons(polygons);
+                }
+            }
+        }
+    }
+}
+
+fn arc_point(radius: f32, angle: f32) -> Vec2 {
+    Vec2::new(angle.cos(), angle.sin()) * radius
+}
+
+/// Rotates a point on the XZ-plane in the same direction as a rotation around the Y-axis does.
+fn rotate(point: Vec2, degrees: f32) -> Vec2 {
+    Vec2::from_angle(-radians(degrees)).rotate(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(position: Vec3, rotation: f32) -> Transform {
+        Transform {
+            position,
+            rotation: Vec3::new(0.0, rotation, 0.0),
+            scale: Vec3::ONE,
+        }
+    }
+
+    #[test]
+    fn rectangle_turns_with_its_transform() {
+        let polygons =
+            Collider::rectangle(2.0, 4.0).polygons(&transform(Vec3::new(3.0, 0.0, 1.0), 90.0));
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].len(), 4);
+        for corner in [
+            Vec2::new(5.0, 0.0),
+            Vec2::new(5.0, 2.0),
+            Vec2::new(1.0, 2.0),
+            Vec2::new(1.0, 0.0),
+        ] {
+            assert!(polygons[0]
+                .iter()
+                .any(|point| point.abs_diff_eq(corner, 1e-5)));
+        }
+    }
+
+    #[test]
+    fn capsule_spans_length_and_radius() {
+        let polygons = Collider::capsule(0.5, 2.0).polygons(&transform(Vec3::ZERO, 0.0));
+        let (min, max) = polygons[0]
+            .iter()
+            .fold((Vec2::INFINITY, Vec2::NEG_INFINITY), |(min, max), p| {
+                (min.min(*p), max.max(*p))
+            });
+        assert!(min.abs_diff_eq(Vec2::new(-0.5, -1.5), 1e-5));
+        assert!(max.abs_diff_eq(Vec2::new(0.5, 1.5), 1e-5));
+    }
+
+    #[test]
+    fn from_bounds_centres_box() {
+        let bounds = Bounds {
+            min: Vec3::new(-1.0, 0.0, 0.0),
+            max: Vec3::new(3.0, 2.0, 1.0),
+        };
+        assert_eq!(
+            Collider::from_bounds(&bounds),
+            Collider::rectangle(4.0, 1.0).with_offset(Vec2::new(1.0, 0.5))
+        );
+    }
+
+    #[test]
+    fn compound_rotation_moves_offsets() {
+        let collider =
+            Collider::Compound(vec![Collider::circle(1.0).with_offset(Vec2::new(2.0, 0.0))])
+                .with_rotation(90.0);
+        let Collider::Compound(shapes) = collider else {
+            unreachable!()
+        };
+        assert!(shapes[0].offset().abs_diff_eq(Vec2::new(0.0, -2.0), 1e-5));
+    }
+}
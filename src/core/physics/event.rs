@@ -0,0 +1,144 @@
+use crate::core::ecs::EntityID;
+use crate::core::physics::Contact;
+use glam::Vec3;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CollisionPhase {
+    /// The two bodies started touching during this step.
+    Begin,
+    /// The two bodies were already touching and still are.
+    Persist,
+    /// The two bodies stopped touching, or one of them was freed. The contact is where the two
+    /// were last seen touching.
+    End,
+}
+
+/// Two bodies touching each other. The first body is always a dynamic body, while the second body
+/// may be of any kind. The normal points out of the second body towards the first, and the impact
+/// speed is how fast the two were closing in on each other along it, in units per second, before
+/// the collision pushed them apart.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CollisionEvent {
+    pub phase: CollisionPhase,
+    pub a: EntityID,
+    pub b: EntityID,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub impact_speed: f32,
+}
+
+impl CollisionEvent {
+    pub fn involves(&self, entity: EntityID) -> bool {
+        self.a == entity || self.b == entity
+    }
+
+    /// The body on the other side of the collision from `entity`.
+    pub fn other(&self, entity: EntityID) -> Option<EntityID> {
+        if entity == self.a {
+            Some(self.b)
+        } else if entity == self.b {
+            Some(self.a)
+        } else {
+            None
+        }
+    }
+}
+
+/// Events emitted by the physics since the last time they were drained, which may span any number
+/// of steps. Whether two bodies are touching is only ever as of the latest step. Worlds without
+/// this resource still resolve collisions, they just do not report them.
+#[derive(Default)]
+pub struct CollisionEvents {
+    events: Vec<CollisionEvent>,
+    touching: BTreeMap<(EntityID, EntityID), Contact>,
+    previous: BTreeMap<(EntityID, EntityID), Contact>,
+    /// Contacts found by sweeping a fast body into a static body, which leaves the two just short
+    /// of overlapping. These count as touching for one more step, unless the bodies are seen
+    /// touching again by then.
+    swept: BTreeMap<(EntityID, EntityID), Contact>,
+}
+
+impl CollisionEvents {
+    pub fn iter(&self) -> impl Iterator<Item = &CollisionEvent> {
+        self.events.iter()
+    }
+
+    /// The events where `entity` started touching another body.
+    pub fn began(&self, entity: EntityID) -> impl Iterator<Item = &CollisionEvent> {
+        self.events
+            .iter()
+            .filter(move |event| event.phase == CollisionPhase::Begin && event.involves(entity))
+    }
+
+    pub fn touching(&self, a: EntityID, b: EntityID) -> bool {
+        self.touching.contains_key(&(a, b)) || self.touching.contains_key(&(b, a))
+    }
+
+    pub fn drain(&mut self) -> Vec<CollisionEvent> {
+        self.events.drain(..).collect()
+    }
+
+    pub(super) fn begin_step(&mut self) {
+        self.previous = std::mem::take(&mut self.touching);
+    }
+
+    /// Records that `a` touches `b`, where any further contacts between the same two bodies during
+    /// the same step are left out.
+    pub(super) fn record(&mut self, a: EntityID, b: EntityID, contact: Contact, impact_speed: f32) {
+        if self.touching.contains_key(&(a, b)) {
+            return;
+        }
+        let phase = if self.previous.contains_key(&(a, b)) {
+            CollisionPhase::Persist
+        } else {
+            CollisionPhase::Begin
+        };
+        self.touching.insert((a, b), contact);
+        self.events.push(CollisionEvent {
+            phase,
+            a,
+            b,
+            point: contact.point,
+            normal: contact.normal,
+            impact_speed,
+        });
+    }
+
+    /// Records a contact found by a sweep, see `swept`.
+    pub(super) fn record_swept(
+        &mut self,
+        a: EntityID,
+        b: EntityID,
+        contact: Contact,
+        impact_speed: f32,
+    ) {
+        self.record(a, b, contact, impact_speed);
+        self.swept.insert((a, b), contact);
+    }
+
+    /// Reports every pair which was touching during the previous step but no longer is, other than
+    /// those kept touching by a sweep.
+    pub(super) fn end_step(&mut self) {
+        let swept = std::mem::take(&mut self.swept);
+        for (&(a, b), contact) in &self.previous {
+            if self.touching.contains_key(&(a, b)) {
+                continue;
+            }
+            let phase = if swept.contains_key(&(a, b)) {
+                self.touching.insert((a, b), *contact);
+                CollisionPhase::Persist
+            } else {
+                CollisionPhase::End
+            };
+            self.events.push(CollisionEvent {
+                phase,
+                a,
+                b,
+                point: contact.point,
+                normal: contact.normal,
+                impact_speed: 0.0,
+            });
+        }
+    }
+}
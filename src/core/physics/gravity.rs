@@ -0,0 +1,275 @@
+use crate::core::ecs::component::Transform;
+use crate::core::ecs::{Query, ECS};
+use crate::core::physics::{flatten_vec3, vertical_extent, DynamicPhysicsBody, PhysicsBody};
+use crate::core::radians;
+use glam::{Mat4, Vec2, Vec3};
+
+/// How far below the bottom of a body a surface may be for the body to still be standing on it.
+/// Anything further down has the body falling towards it instead.
+const STEP_HEIGHT: f32 = 0.5;
+/// How far above a surface a body may hover while still counting as standing on it, which keeps
+/// bodies following a slope from leaving it over rounding errors.
+const GROUND_TOLERANCE: f32 = 0.05;
+
+/// Pulls dynamic bodies down along the Y-axis and keeps them on top of whatever they are standing
+/// on. Levels without this resource stay flat, with every body sliding around on the XZ-plane.
+pub struct Gravity {
+    pub acceleration: f32,
+    /// The height of the floor spanning the entire world, bodies never fall below it.
+    pub floor: f32,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self {
+            acceleration: 20.0,
+            floor: 0.0,
+        }
+    }
+}
+
+/// A surface which bodies drive onto rather than into, such as a jump ramp or the deck of an
+/// overpass. The surface covers `width` and `depth` in the same way as a trigger does, starts out
+/// at the height of the transform along its back edge and climbs by `rise` towards its front edge
+/// (along the Z-axis). All sizes are scaled by the transform.
+#[derive(Copy, Clone)]
+pub struct Ground {
+    pub width: f32,
+    pub depth: f32,
+    pub rise: f32,
+}
+
+impl Ground {
+    pub fn flat(width: f32, depth: f32) -> Self {
+        Self {
+            width,
+            depth,
+            rise: 0.0,
+        }
+    }
+
+    pub fn ramp(width: f32, depth: f32, rise: f32) -> Self {
+        Self { width, depth, rise }
+    }
+
+    /// The height of the surface at `point` along with how steeply it climbs along the XZ-plane,
+    /// or nothing if the point is not above the surface.
+    fn surface_at(&self, transform: &Transform, point: Vec2) -> Option<(f32, Vec3)> {
+        let matrix = Mat4::from_translation(transform.position)
+            * Mat4::from_scale(transform.scale)
+            * Mat4::from_rotation_y(radians(transform.rotation.y));
+        let local =
+            matrix
+                .inverse()
+                .transform_point3(Vec3::new(point.x, transform.position.y, point.y));
+        if local.x.abs() > self.width / 2.0 || local.z.abs() > self.depth / 2.0 {
+            return None;
+        }
+        let climb = self.rise * transform.scale.y;
+        let height = transform.position.y + climb * (local.z / self.depth + 0.5);
+        let forward = matrix.transform_vector3(Vec3::Z);
+        let slope = forward.normalize_or_zero() * climb / (self.depth * forward.length());
+        Some((height, slope))
+    }
+}
+
+/// Lets dynamic bodies fall until they land on the floor, on some ground or on top of a static
+/// body with a height. A body standing on a slope is given the vertical momentum needed to follow
+/// it, which is what launches bodies into the air once they drive off the end of a ramp. Bodies
+/// only ever stand on what is right below their centre, and never on top of each other. Expected
+/// to run after the velocity system.
+pub fn gravity_system(ecs: &mut ECS, delta_time: f32) {
+    let Some(gravity) = ecs.get_resource::<Gravity>() else {
+        return;
+    };
+    let (acceleration, floor) = (gravity.acceleration, gravity.floor);
+    let grounds: Vec<(Transform, Ground)> = ecs
+        .query(&Query::new().with::<Transform>().with::<Ground>().build())
+        .into_iter()
+        .map(|id| {
+            (
+                ecs.clone_component::<Transform>(id).unwrap(),
+                ecs.clone_component::<Ground>(id).unwrap(),
+            )
+        })
+        .collect();
+    let statics: Vec<(Transform, PhysicsBody)> = ecs
+        .query(
+            &Query::new()
+                .with::<Transform>()
+                .with::<PhysicsBody>()
+                .build(),
+        )
+        .into_iter()
+        .map(|id| {
+            (
+                ecs.clone_component::<Transform>(id).unwrap(),
+                ecs.clone_component::<PhysicsBody>(id).unwrap(),
+            )
+        })
+        .filter(|(_, body)| body.height.is_some())
+        .collect();
+    let bodies = ecs.query(
+        &Query::new()
+            .with::<Transform>()
+            .with::<DynamicPhysicsBody>()
+            .build(),
+    );
+    for id in bodies {
+        let transform = ecs.clone_component::<Transform>(id).unwrap();
+        let centre = flatten_vec3(transform.position);
+        let bottom = transform.position.y;
+        let surfaces = grounds
+            .iter()
+            .filter_map(|(transform, ground)| ground.surface_at(transform, centre))
+            .chain(statics.iter().filter_map(|(transform, body)| {
+                let top = vertical_extent(transform, body).1;
+                let below = body
+                    .collider
+                    .polygons(transform)
+                    .iter()
+                    .any(|polygon| contains_point(polygon, centre));
+                below.then_some((top, Vec3::ZERO))
+            }));
+        let support = surfaces
+            .filter(|(height, _)| *height <= bottom + STEP_HEIGHT)
+            .chain([(floor, Vec3::ZERO)])
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap();
+        let grounded = bottom <= support.0 + GROUND_TOLERANCE;
+        ecs.update_component::<DynamicPhysicsBody>(id, &mut |mut body| {
+            body.grounded = grounded;
+            if grounded {
+                let horizontal = Vec3::new(body.force.x, 0.0, body.force.z);
+                body.force.y = horizontal.dot(support.1);
+            } else {
+                body.force.y -= body.base.mass * acceleration * delta_time;
+            }
+            body
+        })
+        .unwrap();
+        if grounded {
+            ecs.update_component::<Transform>(id, &mut |mut transform| {
+                transform.position.y = support.0;
+                transform
+            })
+            .unwrap();
+        }
+    }
+}
+
+/// Checks whether a point lies within a convex polygon, no matter which way the polygon winds.
+fn contains_point(polygon: &[Vec2], point: Vec2) -> bool {
+    let sides = (0..polygon.len()).map(|i| {
+        let edge = polygon[(i + 1) % polygon.len()] - polygon[i];
+        edge.perp_dot(point - polygon[i])
+    });
+    let (mut left, mut right) = (false, false);
+    for side in sides {
+        left |= side > 0.0;
+        right |= side < 0.0;
+    }
+    !(left && right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::{ECSBuilder, EntityID};
+    use crate::core::physics::collider::Collider;
+    use crate::core::physics::material::PhysicsMaterial;
+    use crate::core::physics::{velocity_system, ALL_LAYERS, DEFAULT_LAYER};
+
+    fn world() -> ECS {
+        ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_component::<Ground>()
+            .with_resource(Gravity::default())
+            .build()
+    }
+
+    fn spawn_body(ecs: &mut ECS, position: Vec3, force: Vec3) -> EntityID {
+        let id = ecs.create_entity();
+        let transform = Transform {
+            position,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(id, transform).unwrap();
+        let body = DynamicPhysicsBody {
+            base: PhysicsBody {
+                mass: 1.0,
+                collider: Collider::rectangle(1.0, 1.0),
+                layer: DEFAULT_LAYER,
+                collides_with: ALL_LAYERS,
+                material: PhysicsMaterial::default(),
+                height: Some(1.0),
+            },
+            force,
+            velocity: Vec3::ZERO,
+            torque: 0.0,
+            angular_velocity: 0.0,
+            inertia: 1.0,
+            grounded: true,
+            rest_time: 0.0,
+        };
+        ecs.attach_component(id, body).unwrap();
+        id
+    }
+
+    fn step(ecs: &mut ECS, id: EntityID) -> Vec3 {
+        velocity_system(ecs, 1.0 / 60.0);
+        gravity_system(ecs, 1.0 / 60.0);
+        ecs.clone_component::<Transform>(id).unwrap().position
+    }
+
+    #[test]
+    fn bodies_fall_onto_the_floor() {
+        let mut ecs = world();
+        let id = spawn_body(&mut ecs, Vec3::new(0.0, 3.0, 0.0), Vec3::ZERO);
+        step(&mut ecs, id);
+        assert!(
+            !ecs.clone_component::<DynamicPhysicsBody>(id)
+                .unwrap()
+                .grounded
+        );
+        for _ in 0..120 {
+            step(&mut ecs, id);
+        }
+        assert_eq!(
+            ecs.clone_component::<Transform>(id).unwrap().position.y,
+            0.0
+        );
+        assert!(
+            ecs.clone_component::<DynamicPhysicsBody>(id)
+                .unwrap()
+                .grounded
+        );
+    }
+
+    #[test]
+    fn ramps_launch_bodies_into_the_air() {
+        let mut ecs = world();
+        let id = spawn_body(
+            &mut ecs,
+            Vec3::new(0.0, 0.0, -1.9),
+            Vec3::new(0.0, 0.0, 20.0),
+        );
+        let ramp = ecs.create_entity();
+        let transform = Transform {
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(ramp, transform).unwrap();
+        ecs.attach_component(ramp, Ground::ramp(4.0, 4.0, 2.0))
+            .unwrap();
+
+        let heights: Vec<f32> = (0..120).map(|_| step(&mut ecs, id).y).collect();
+        let highest = heights.iter().copied().fold(0.0, f32::max);
+        assert!(highest > 2.0, "only reached {}", highest);
+        assert_eq!(*heights.last().unwrap(), 0.0);
+    }
+}
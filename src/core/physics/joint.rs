@@ -0,0 +1,306 @@
+use crate::core::ecs::component::Transform;
+use crate::core::ecs::{EntityID, Query, ECS};
+use crate::core::physics::{flatten_vec3, DynamicPhysicsBody};
+use crate::core::radians;
+use glam::{Mat4, Vec2, Vec3};
+
+/// How many times every joint is solved per frame. Joints sharing a body pull on each other, which
+/// takes a few rounds to settle for longer chains.
+const JOINT_ITERATIONS: usize = 4;
+/// How much of the stretch of a joint is removed every frame, on top of stopping it from
+/// stretching any further. Removing all of it at once makes chains jitter.
+const JOINT_STIFFNESS: f32 = 0.2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum JointKind {
+    /// Keeps the anchors exactly `length` units apart, like a tow bar.
+    Distance { length: f32 },
+    /// Keeps the anchors at most `length` units apart, while letting them get closer.
+    Rope { length: f32 },
+    /// Pins the anchors together while letting the bodies turn freely around them, like a trailer
+    /// hitch or the hinge of a gate.
+    Hinge,
+}
+
+/// Connects two bodies at an anchor on each of them. The anchors are given in the space of their
+/// bodies in the same way as the offset of a collider, meaning the Y-component is along the
+/// Z-axis. Both bodies are usually dynamic, while any other entity with a transform, such as a
+/// static body, acts as a fixed anchor point.
+///
+/// Joints are their own entities, and bodies connected by a joint still collide with each other,
+/// so the anchors should sit just outside of the colliders or the bodies put on separate layers.
+#[derive(Debug, Copy, Clone)]
+pub struct Joint {
+    pub a: EntityID,
+    pub b: EntityID,
+    pub anchor_a: Vec2,
+    pub anchor_b: Vec2,
+    pub kind: JointKind,
+}
+
+impl Joint {
+    pub fn distance(a: EntityID, anchor_a: Vec2, b: EntityID, anchor_b: Vec2, length: f32) -> Self {
+        Self {
+            a,
+            b,
+            anchor_a,
+            anchor_b,
+            kind: JointKind::Distance { length },
+        }
+    }
+
+    pub fn rope(a: EntityID, anchor_a: Vec2, b: EntityID, anchor_b: Vec2, length: f32) -> Self {
+        Self {
+            a,
+            b,
+            anchor_a,
+            anchor_b,
+            kind: JointKind::Rope { length },
+        }
+    }
+
+    pub fn hinge(a: EntityID, anchor_a: Vec2, b: EntityID, anchor_b: Vec2) -> Self {
+        Self {
+            a,
+            b,
+            anchor_a,
+            anchor_b,
+            kind: JointKind::Hinge,
+        }
+    }
+}
+
+/// One end of a joint, where a missing body means the end cannot be moved.
+struct End {
+    id: EntityID,
+    /// Where the anchor is relative to the centre of the body, in world space.
+    offset: Vec3,
+    body: Option<DynamicPhysicsBody>,
+}
+
+impl End {
+    fn load(ecs: &ECS, id: EntityID, anchor: Vec2) -> Option<Self> {
+        let transform = ecs.clone_component::<Transform>(id)?;
+        let matrix = Mat4::from_scale(transform.scale)
+            * Mat4::from_rotation_y(radians(transform.rotation.y));
+        let offset = matrix.transform_vector3(Vec3::new(anchor.x, 0.0, anchor.y));
+        Some(Self {
+            id,
+            offset,
+            body: ecs.clone_component::<DynamicPhysicsBody>(id),
+        })
+    }
+
+    fn position(&self, ecs: &ECS) -> Vec2 {
+        let transform = ecs.clone_component::<Transform>(self.id).unwrap();
+        flatten_vec3(transform.position + self.offset)
+    }
+
+    fn velocity(&self) -> Vec3 {
+        self.body
+            .as_ref()
+            .map_or(Vec3::ZERO, |body| body.point_velocity(self.offset))
+    }
+
+    fn inverse_mass(&self, normal: Vec3) -> f32 {
+        self.body
+            .as_ref()
+            .map_or(0.0, |body| body.inverse_effective_mass(self.offset, normal))
+    }
+
+    fn sleeping(&self) -> bool {
+        self.body.as_ref().is_none_or(|body| body.sleeping())
+    }
+}
+
+/// Keeps jointed bodies together by exchanging momentum between them, much like a collision does.
+/// Any stretch left over from the previous frame is worked off gradually. Expected to run after
+/// the collision system and before the velocity system.
+pub fn joint_system(ecs: &mut ECS, delta_time: f32) {
+    let joints: Vec<Joint> = ecs
+        .query(&Query::new().with::<Joint>().build())
+        .into_iter()
+        .map(|id| ecs.clone_component::<Joint>(id).unwrap())
+        .collect();
+    for _ in 0..JOINT_ITERATIONS {
+        for joint in joints.iter() {
+            solve_joint(ecs, joint, delta_time);
+        }
+    }
+}
+
+fn solve_joint(ecs: &mut ECS, joint: &Joint, delta_time: f32) {
+    // Joints whose bodies have since been removed do nothing
+    let (Some(mut a), Some(mut b)) = (
+        End::load(ecs, joint.a, joint.anchor_a),
+        End::load(ecs, joint.b, joint.anchor_b),
+    ) else {
+        return;
+    };
+    if a.sleeping() && b.sleeping() {
+        return;
+    }
+    let separation = b.position(ecs) - a.position(ecs);
+    let relative_velocity = b.velocity() - a.velocity();
+    let (length, slack) = match joint.kind {
+        JointKind::Distance { length } => (length, false),
+        JointKind::Rope { length } => (length, true),
+        JointKind::Hinge => (0.0, false),
+    };
+    let stretch = separation.length() - length;
+    if slack && stretch <= 0.0 {
+        return;
+    }
+    // A hinge whose anchors line up is held along whichever way they are drifting apart
+    let normal = match separation.try_normalize() {
+        Some(direction) => Vec3::new(direction.x, 0.0, direction.y),
+        None => Vec3::new(relative_velocity.x, 0.0, relative_velocity.z).normalize_or_zero(),
+    };
+    if normal == Vec3::ZERO {
+        return;
+    }
+
+    let target = -stretch * JOINT_STIFFNESS / delta_time;
+    let inverse_mass = a.inverse_mass(normal) + b.inverse_mass(normal);
+    if inverse_mass == 0.0 {
+        return;
+    }
+    let mut magnitude = (target - relative_velocity.dot(normal)) / inverse_mass;
+    if slack {
+        // A rope can only ever pull
+        magnitude = magnitude.min(0.0);
+    }
+    let impulse = normal * magnitude;
+    for (end, impulse) in [(&mut a, -impulse), (&mut b, impulse)] {
+        if let Some(mut body) = end.body.take() {
+            body.apply_impulse(impulse, end.offset);
+            ecs.update_component::<DynamicPhysicsBody>(end.id, &mut |_| body.clone())
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::ECSBuilder;
+    use crate::core::physics::collider::Collider;
+    use crate::core::physics::material::PhysicsMaterial;
+    use crate::core::physics::{
+        box_inertia, velocity_system, PhysicsBody, ALL_LAYERS, DEFAULT_LAYER,
+    };
+
+    fn world() -> ECS {
+        ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_component::<Joint>()
+            .build()
+    }
+
+    fn spawn(ecs: &mut ECS, position: Vec3, force: Option<Vec3>) -> EntityID {
+        let id = ecs.create_entity();
+        let transform = Transform {
+            position,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(id, transform).unwrap();
+        if let Some(force) = force {
+            let body = DynamicPhysicsBody {
+                base: PhysicsBody {
+                    mass: 1.0,
+                    collider: Collider::rectangle(1.0, 1.0),
+                    layer: DEFAULT_LAYER,
+                    collides_with: ALL_LAYERS,
+                    material: PhysicsMaterial::default(),
+                    height: None,
+                },
+                force,
+                velocity: Vec3::ZERO,
+                torque: 0.0,
+                angular_velocity: 0.0,
+                inertia: box_inertia(1.0, 1.0, 1.0),
+                grounded: true,
+                rest_time: 0.0,
+            };
+            ecs.attach_component(id, body).unwrap();
+        }
+        id
+    }
+
+    fn step(ecs: &mut ECS, frames: usize) {
+        for _ in 0..frames {
+            joint_system(ecs, 1.0 / 60.0);
+            velocity_system(ecs, 1.0 / 60.0);
+        }
+    }
+
+    fn position(ecs: &ECS, id: EntityID) -> Vec3 {
+        ecs.clone_component::<Transform>(id).unwrap().position
+    }
+
+    #[test]
+    fn distance_joint_tows_trailer() {
+        let mut ecs = world();
+        let car = spawn(&mut ecs, Vec3::ZERO, Some(Vec3::new(20.0, 0.0, 0.0)));
+        let trailer = spawn(&mut ecs, Vec3::new(-2.0, 0.0, 0.0), Some(Vec3::ZERO));
+        let joint = ecs.create_entity();
+        ecs.attach_component(
+            joint,
+            Joint::distance(car, Vec2::ZERO, trailer, Vec2::ZERO, 2.0),
+        )
+        .unwrap();
+
+        step(&mut ecs, 30);
+        let (car, trailer) = (position(&ecs, car), position(&ecs, trailer));
+        assert!(trailer.x > -2.0);
+        assert!((car.distance(trailer) - 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn rope_joint_only_pulls_when_taut() {
+        let mut ecs = world();
+        let post = spawn(&mut ecs, Vec3::ZERO, None);
+        let body = spawn(
+            &mut ecs,
+            Vec3::new(1.0, 0.0, 0.0),
+            Some(Vec3::new(-5.0, 0.0, 0.0)),
+        );
+        let joint = ecs.create_entity();
+        ecs.attach_component(joint, Joint::rope(post, Vec2::ZERO, body, Vec2::ZERO, 3.0))
+            .unwrap();
+
+        step(&mut ecs, 5);
+        assert!(position(&ecs, body).x < 1.0);
+        ecs.update_component::<DynamicPhysicsBody>(body, &mut |mut body| {
+            body.force = Vec3::new(50.0, 0.0, 0.0);
+            body
+        })
+        .unwrap();
+        step(&mut ecs, 60);
+        assert!(position(&ecs, body).x < 3.1);
+    }
+
+    #[test]
+    fn hinge_joint_swings_gate_around_post() {
+        let mut ecs = world();
+        let post = spawn(&mut ecs, Vec3::ZERO, None);
+        let gate = spawn(&mut ecs, Vec3::new(2.0, 0.0, 0.0), Some(Vec3::ZERO));
+        let joint = ecs.create_entity();
+        let hinge = Joint::hinge(post, Vec2::ZERO, gate, Vec2::new(-2.0, 0.0));
+        ecs.attach_component(joint, hinge).unwrap();
+
+        // Pushing the far end of the gate turns it rather than dragging it away from the post
+        ecs.update_component::<DynamicPhysicsBody>(gate, &mut |mut body| {
+            body.apply_impulse(Vec3::new(0.0, 0.0, 2.0), Vec3::new(2.0, 0.0, 0.0));
+            body
+        })
+        .unwrap();
+        step(&mut ecs, 30);
+        let transform = ecs.clone_component::<Transform>(gate).unwrap();
+        let hinge = End::load(&ecs, gate, Vec2::new(-2.0, 0.0)).unwrap();
+        assert!(hinge.position(&ecs).length() < 0.1);
+        assert!(transform.rotation.y.abs() > 1.0);
+    }
+}
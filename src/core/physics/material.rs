@@ -0,0 +1,170 @@
+use crate::core::ecs::{Query, ECS};
+use crate::core::physics::trigger::Trigger;
+use crate::core::physics::vehicle::Vehicle;
+
+/// Decides how the properties of two materials in contact are combined into one. When two materials
+/// disagree on the rule, the rule declared last in this enum wins.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CombineRule {
+    #[default]
+    Average,
+    Minimum,
+    Multiply,
+    Maximum,
+}
+
+impl CombineRule {
+    pub fn combine(self, other: CombineRule, a: f32, b: f32) -> f32 {
+        match self.max(other) {
+            CombineRule::Average => (a + b) / 2.0,
+            CombineRule::Minimum => a.min(b),
+            CombineRule::Multiply => a * b,
+            CombineRule::Maximum => a.max(b),
+        }
+    }
+}
+
+/// Describes the surface of a physics body. Friction is how much two bodies resist sliding along
+/// each other, and restitution is how much of the momentum heading into a collision is bounced
+/// back out of it. The drag is how much of its linear and angular momentum a body loses every
+/// second while moving freely.
+///
+/// The default material is that of the hull of a body, slippery enough for a car to scrape along a
+/// wall rather than sticking to it. Asphalt grips far better, as it describes the road under the
+/// tires and only ever takes part in the traction of vehicles.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicsMaterial {
+    pub friction: f32,
+    pub restitution: f32,
+    pub linear_drag: f32,
+    pub angular_drag: f32,
+    pub friction_combine: CombineRule,
+    pub restitution_combine: CombineRule,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            friction: 0.1,
+            restitution: 0.2,
+            linear_drag: 4.0,
+            angular_drag: 4.0,
+            friction_combine: CombineRule::Average,
+            restitution_combine: CombineRule::Average,
+        }
+    }
+}
+
+impl PhysicsMaterial {
+    pub fn asphalt() -> Self {
+        Self {
+            friction: 1.0,
+            ..Self::default()
+        }
+    }
+
+    pub fn grass() -> Self {
+        Self {
+            friction: 0.6,
+            ..Self::default()
+        }
+    }
+
+    pub fn ice() -> Self {
+        Self {
+            friction: 0.15,
+            restitution: 0.1,
+            ..Self::default()
+        }
+    }
+
+    pub fn oil() -> Self {
+        Self {
+            friction: 0.05,
+            ..Self::default()
+        }
+    }
+
+    pub fn combined_friction(&self, other: &PhysicsMaterial) -> f32 {
+        self.friction_combine
+            .combine(other.friction_combine, self.friction, other.friction)
+    }
+
+    pub fn combined_restitution(&self, other: &PhysicsMaterial) -> f32 {
+        self.restitution_combine.combine(
+            other.restitution_combine,
+            self.restitution,
+            other.restitution,
+        )
+    }
+}
+
+/// Turns a trigger into an area of ground covered by some other material than asphalt, such as a
+/// patch of grass or an oil slick. Vehicles driving over it have their grip changed by the
+/// friction of the material.
+#[derive(Copy, Clone)]
+pub struct SurfaceZone {
+    pub material: PhysicsMaterial,
+}
+
+/// Updates the traction of every vehicle from the surface it is currently driving over. Where
+/// surface zones overlap the slipperiest one wins, and vehicles outside of all zones are driving
+/// on asphalt. Expected to run after the trigger system.
+pub fn surface_system(ecs: &mut ECS) {
+    let zones = ecs.query(&Query::new().with::<SurfaceZone>().with::<Trigger>().build());
+    let zones: Vec<_> = zones
+        .into_iter()
+        .map(|id| {
+            (
+                ecs.clone_component::<SurfaceZone>(id).unwrap().material,
+                ecs.clone_component::<Trigger>(id).unwrap(),
+            )
+        })
+        .collect();
+    let vehicles = ecs.query(&Query::new().with::<Vehicle>().build());
+    for id in vehicles {
+        let surface = zones
+            .iter()
+            .filter(|(_, trigger)| trigger.overlapping().contains(&id))
+            .map(|(material, _)| *material)
+            .min_by(|a, b| a.friction.total_cmp(&b.friction))
+            .unwrap_or_else(PhysicsMaterial::asphalt);
+        ecs.update_component::<Vehicle>(id, &mut |mut vehicle| {
+            vehicle.traction = vehicle.tires.combined_friction(&surface);
+            vehicle
+        })
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combine_rule_picks_highest_rule() {
+        assert_eq!(
+            CombineRule::Average.combine(CombineRule::Average, 0.2, 0.6),
+            0.4
+        );
+        assert_eq!(
+            CombineRule::Average.combine(CombineRule::Minimum, 0.2, 0.6),
+            0.2
+        );
+        assert_eq!(
+            CombineRule::Maximum.combine(CombineRule::Minimum, 0.2, 0.6),
+            0.6
+        );
+    }
+
+    #[test]
+    fn tires_combine_with_surfaces() {
+        let tires = PhysicsMaterial {
+            friction: 1.0,
+            friction_combine: CombineRule::Multiply,
+            ..PhysicsMaterial::default()
+        };
+        assert_eq!(tires.combined_friction(&PhysicsMaterial::grass()), 0.6);
+        assert_eq!(tires.combined_friction(&PhysicsMaterial::oil()), 0.05);
+    }
+}
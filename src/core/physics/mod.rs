@@ -1,9 +1,64 @@
 use crate::core::ecs::component::Transform;
-use crate::core::ecs::{Query, ECS};
-use crate::core::radians;
-use glam::{Mat4, Vec2, Vec3};
+use crate::core::ecs::{EcsError, EntityID, Query, ECS};
+use crate::core::physics::broadphase::{Aabb, Broadphase};
+use crate::core::physics::collider::Collider;
+use crate::core::physics::event::CollisionEvents;
+use crate::core::physics::material::PhysicsMaterial;
+use crate::core::physics::raycast::sweep_polygon;
+use glam::{Vec2, Vec3};
 
-struct Line(Vec2, Vec2);
+pub mod broadphase;
+pub mod collider;
+pub mod event;
+pub mod gravity;
+pub mod joint;
+pub mod material;
+pub mod raycast;
+pub mod timestep;
+pub mod trigger;
+pub mod vehicle;
+
+/// The share of its own thickness a body may move in a single frame before it is swept along its
+/// path rather than moved straight to its new position.
+const CCD_THRESHOLD: f32 = 0.5;
+/// How many times a swept body may hit and slide along a new surface during a single frame.
+const CCD_MAX_SWEEPS: usize = 3;
+/// The gap left between a swept body and the surface it hits, so that it does not start the next
+/// frame touching it.
+const CCD_SKIN: f32 = 0.01;
+/// How far two bodies have to overlap along the Y-axis before they collide, letting a body rest on
+/// top of another without the two colliding.
+const VERTICAL_SKIN: f32 = 0.01;
+
+/// The speed, in units per second, below which a body is considered to be at rest.
+const SLEEP_SPEED: f32 = 0.05;
+/// The angular speed, in radians per second, below which a body is considered to be at rest.
+const SLEEP_ANGULAR_SPEED: f32 = 0.05;
+/// How long a body has to stay at rest before it is put to sleep.
+const SLEEP_DELAY: f32 = 0.5;
+
+/// Describes how two bodies overlap. The normal points out of the second body towards the first,
+/// meaning that moving the first body `depth` units along it separates the two. The point is where
+/// the two bodies are touching, which is what decides whether a collision makes them spin.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Contact {
+    pub normal: Vec3,
+    pub depth: f32,
+    pub point: Vec3,
+}
+
+/// Advances the physics by a single step, running every physics system in the order they expect
+/// to run in. Gameplay systems feeding input into the physics are expected to run right before.
+pub fn physics_system(ecs: &mut ECS, delta_time: f32) {
+    vehicle::vehicle_system(ecs, delta_time);
+    kinematic_system(ecs, delta_time);
+    collision_system(ecs);
+    joint::joint_system(ecs, delta_time);
+    velocity_system(ecs, delta_time);
+    gravity::gravity_system(ecs, delta_time);
+    trigger::trigger_system(ecs);
+    material::surface_system(ecs);
+}
 
 pub fn collision_system(ecs: &mut ECS) {
     let dynamic = ecs.query(
@@ -12,110 +67,560 @@ pub fn collision_system(ecs: &mut ECS) {
             .with::<DynamicPhysicsBody>()
             .build(),
     );
-    let statics = ecs.query(
+    if let Some(events) = ecs.get_resource_mut::<CollisionEvents>() {
+        events.begin_step();
+    }
+    update_kinematic_broadphase(ecs);
+    for id in dynamic.iter().copied() {
+        let transform = ecs.clone_component::<Transform>(id).unwrap();
+        let body = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
+        let bounds = Aabb::from_points(&body.base.collider.polygons(&transform).concat());
+        // Static and kinematic bodies are both immovable as far as the dynamic body is concerned.
+        // A body wedged into a corner touches several of them at once, and is pushed out of each
+        // of them in turn.
+        let broadphase = ecs.get_resource::<Broadphase>().unwrap();
+        let others: Vec<EntityID> = broadphase
+            .statics
+            .query(&bounds)
+            .into_iter()
+            .chain(broadphase.kinematics.query(&bounds))
+            .collect();
+        for other in others {
+            resolve_immovable_pair(ecs, id, other);
+        }
+    }
+    update_dynamic_broadphase(ecs, &dynamic);
+    // Every pair of dynamic bodies is visited exactly once by only pairing each body with the
+    // bodies after it, resolving a pair from both ends would apply its impulse twice.
+    for a in dynamic.iter().copied() {
+        let broadphase = ecs.get_resource::<Broadphase>().unwrap();
+        let candidates = broadphase
+            .dynamics
+            .query(&Aabb::from_points(&broadphase.polygons(a).concat()));
+        for b in candidates.into_iter().filter(|b| *b > a) {
+            resolve_dynamic_pair(ecs, a, b);
+        }
+    }
+    if let Some(events) = ecs.get_resource_mut::<CollisionEvents>() {
+        events.end_step();
+    }
+}
+
+/// Pushes a dynamic body out of a static or kinematic body. The shape of the dynamic body is
+/// rebuilt for every pair, since pushing it out of one body may already have pushed it out of
+/// another.
+fn resolve_immovable_pair(ecs: &mut ECS, id: EntityID, other: EntityID) {
+    let transform = ecs.clone_component::<Transform>(id).unwrap();
+    let body = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
+    let (other_body, kinematic) = immovable_body(ecs, other);
+    let other_transform = ecs.clone_component::<Transform>(other).unwrap();
+    // The collision detection works on a 2D plane, bodies are pushed apart along the XZ-plane
+    // only once their vertical extents overlap.
+    if !body.base.interacts_with(&other_body)
+        || !overlaps_vertically(&transform, &body.base, &other_transform, &other_body)
+    {
+        return;
+    }
+    let broadphase = ecs.get_resource::<Broadphase>().unwrap();
+    let contact = intersect_shapes(
+        &body.base.collider.polygons(&transform),
+        broadphase.polygons(other),
+    );
+    let Some(contact) = contact else {
+        return;
+    };
+    let surface_velocity = kinematic.map_or(Vec3::ZERO, |kinematic| {
+        kinematic.point_velocity(contact_offset(&contact, &other_transform))
+    });
+    let offset = contact_offset(&contact, &transform);
+    let impact_speed = (surface_velocity - body.point_velocity(offset)).dot(contact.normal);
+    ecs.update_component::<Transform>(id, &mut |mut transform| {
+        transform.position += contact.normal * contact.depth;
+        transform
+    })
+    .unwrap();
+    ecs.update_component::<DynamicPhysicsBody>(id, &mut |mut body| {
+        bounce_off_immovable(
+            &mut body,
+            offset,
+            surface_velocity,
+            &other_body.material,
+            contact.normal,
+        );
+        body
+    })
+    .unwrap();
+    record_collision(ecs, id, other, contact, impact_speed);
+}
+
+fn record_collision(ecs: &mut ECS, a: EntityID, b: EntityID, contact: Contact, impact_speed: f32) {
+    if let Some(events) = ecs.get_resource_mut::<CollisionEvents>() {
+        events.record(a, b, contact, impact_speed.max(0.0));
+    }
+}
+
+/// Attaches a static body to an entity which already has a transform, adding it to the broadphase
+/// right away. Static bodies are expected to stay put once spawned, which spares the collision
+/// system from looking for new ones every frame.
+pub fn attach_static_body(ecs: &mut ECS, id: EntityID, body: PhysicsBody) -> Result<(), EcsError> {
+    let transform = ecs.clone_component::<Transform>(id).unwrap();
+    let polygons = body.collider.polygons(&transform);
+    ecs.attach_component(id, body)?;
+    if let Some(broadphase) = ecs.get_resource_mut::<Broadphase>() {
+        broadphase.update_static(id, polygons);
+    }
+    Ok(())
+}
+
+/// Frees an entity along with its physics body, taking the body out of the broadphase first.
+pub fn free_body(ecs: &mut ECS, id: EntityID) {
+    if let Some(broadphase) = ecs.get_resource_mut::<Broadphase>() {
+        broadphase.remove(id);
+    }
+    ecs.free_entity(id);
+}
+
+/// Finds the body of a static or kinematic body, the latter of which also has a velocity.
+fn immovable_body(ecs: &ECS, id: EntityID) -> (PhysicsBody, Option<KinematicBody>) {
+    match ecs.clone_component::<KinematicBody>(id) {
+        Some(kinematic) => (kinematic.base.clone(), Some(kinematic)),
+        None => (ecs.clone_component::<PhysicsBody>(id).unwrap(), None),
+    }
+}
+
+/// Kinematic bodies move every frame, just like dynamic bodies.
+fn update_kinematic_broadphase(ecs: &mut ECS) {
+    let kinematic = ecs.query(
         &Query::new()
             .with::<Transform>()
-            .with::<PhysicsBody>()
+            .with::<KinematicBody>()
             .build(),
     );
-    // The real requirement for the collision checker as it stands is to be able to detect
-    // collisions between static and dynamic objects, not between two dynamic objects, since there
-    // will only be one dynamic object on a given level (the player). This is one of those parts of
-    // this program that I will happily revisit once I have my PoC completed.
-    for id in dynamic {
-        let transform = ecs.clone_component::<Transform>(id).unwrap();
-        let body = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
-        let dynamic_bounds = get_bounding_lines(&transform, body.base.width, body.base.depth);
-
-        for other in statics.iter() {
-            let other = other.clone();
-            let other_body = ecs.clone_component::<PhysicsBody>(other).unwrap();
-            let static_bounds = get_bounding_lines(
-                &ecs.clone_component::<Transform>(other).unwrap(),
-                other_body.width,
-                other_body.depth,
-            );
-            // Figure out if the two are overlapping. Since we do not deal with the Y-axis in this
-            // project, the collision detection will work on a 2D plane. In a 3D world this will
-            // effectively mean that all objects have unbounded height
-            if is_rect_intersecting(&dynamic_bounds, &static_bounds) {
-                let mut impulse = -body.force - (body.velocity * other_body.mass);
-                // Adds some extra impulse to the inverted forward vector, otherwise the bodies gets
-                // stuck to each other after colliding.
-                impulse += body.force.normalize_or_zero() * -4.0;
-                ecs.get_component::<DynamicPhysicsBody>(id)
-                    .unwrap()
-                    .borrow_mut()
-                    .downcast_mut::<DynamicPhysicsBody>()
-                    .unwrap()
-                    .force += impulse;
-                break;
-            }
-        }
+    let polygons: Vec<Vec<Vec<Vec2>>> = kinematic
+        .iter()
+        .map(|id| {
+            let body = ecs.clone_component::<KinematicBody>(*id).unwrap();
+            let transform = ecs.clone_component::<Transform>(*id).unwrap();
+            body.base.collider.polygons(&transform)
+        })
+        .collect();
+    let broadphase = ecs.get_resource_mut::<Broadphase>().unwrap();
+    let removed: Vec<EntityID> = broadphase
+        .kinematics
+        .entities()
+        .filter(|id| kinematic.binary_search(id).is_err())
+        .collect();
+    for id in removed {
+        broadphase.remove(id);
+    }
+    for (id, polygons) in kinematic.iter().zip(polygons) {
+        broadphase.update_kinematic(*id, polygons);
     }
 }
 
-fn is_rect_intersecting(a: &[Line; 4], b: &[Line; 4]) -> bool {
-    for al in a {
-        for bl in b.iter().clone() {
-            if is_line_intersecting(&al, bl) {
-                return true;
-            }
-        }
+fn update_dynamic_broadphase(ecs: &mut ECS, dynamic: &[EntityID]) {
+    let polygons: Vec<Vec<Vec<Vec2>>> = dynamic
+        .iter()
+        .map(|id| {
+            let body = ecs.clone_component::<DynamicPhysicsBody>(*id).unwrap();
+            let transform = ecs.clone_component::<Transform>(*id).unwrap();
+            body.base.collider.polygons(&transform)
+        })
+        .collect();
+    let broadphase = ecs.get_resource_mut::<Broadphase>().unwrap();
+    let removed: Vec<EntityID> = broadphase
+        .dynamics
+        .entities()
+        .filter(|id| dynamic.binary_search(id).is_err())
+        .collect();
+    for id in removed {
+        broadphase.remove(id);
+    }
+    for (id, polygons) in dynamic.iter().zip(polygons) {
+        broadphase.update_dynamic(*id, polygons);
     }
-    false
 }
 
-fn is_line_intersecting(a: &Line, b: &Line) -> bool {
-    if (a.0.x == a.1.x && a.0.y == a.1.y) || (b.0.x == b.1.x && b.0.y == b.1.y) {
-        return false;
+fn resolve_dynamic_pair(ecs: &mut ECS, a: EntityID, b: EntityID) {
+    let a_transform = ecs.clone_component::<Transform>(a).unwrap();
+    let a_body = ecs.clone_component::<DynamicPhysicsBody>(a).unwrap();
+    let b_transform = ecs.clone_component::<Transform>(b).unwrap();
+    let b_body = ecs.clone_component::<DynamicPhysicsBody>(b).unwrap();
+    if (a_body.sleeping() && b_body.sleeping())
+        || !a_body.base.interacts_with(&b_body.base)
+        || !overlaps_vertically(&a_transform, &a_body.base, &b_transform, &b_body.base)
+    {
+        return;
     }
-    let denominator = (b.1.y - b.0.y) * (a.1.x - a.0.x) - (b.1.x - b.0.x) * (a.1.y - a.0.y);
-    if denominator == 0.0 {
-        return false;
+    let contact = intersect_shapes(
+        &a_body.base.collider.polygons(&a_transform),
+        &b_body.base.collider.polygons(&b_transform),
+    );
+    let Some(contact) = contact else {
+        return;
+    };
+    // The overlap is split between the two bodies by their inverse mass, which makes the lighter
+    // body move further than the heavier one.
+    let a_inverse_mass = 1.0 / a_body.base.mass;
+    let b_inverse_mass = 1.0 / b_body.base.mass;
+    let correction = contact.normal * contact.depth / (a_inverse_mass + b_inverse_mass);
+    let mut a_body = a_body;
+    let mut b_body = b_body;
+    let (a_offset, b_offset) = (
+        contact_offset(&contact, &a_transform),
+        contact_offset(&contact, &b_transform),
+    );
+    let impact_speed =
+        (b_body.point_velocity(b_offset) - a_body.point_velocity(a_offset)).dot(contact.normal);
+    exchange_momentum(&mut a_body, a_offset, &mut b_body, b_offset, contact.normal);
+    ecs.update_component::<Transform>(a, &mut |mut transform| {
+        transform.position += correction * a_inverse_mass;
+        transform
+    })
+    .unwrap();
+    ecs.update_component::<Transform>(b, &mut |mut transform| {
+        transform.position -= correction * b_inverse_mass;
+        transform
+    })
+    .unwrap();
+    ecs.update_component::<DynamicPhysicsBody>(a, &mut |_| a_body.clone())
+        .unwrap();
+    ecs.update_component::<DynamicPhysicsBody>(b, &mut |_| b_body.clone())
+        .unwrap();
+    record_collision(ecs, a, b, contact, impact_speed);
+}
+
+/// Applies equal and opposite impulses to two colliding bodies along `normal`, which points from
+/// `b` towards `a`. The offsets locate the contact point relative to the centre of each body, so
+/// that a hit off the centre of a body sets it spinning. Since the impulses are equal and opposite
+/// the total momentum of the two bodies is conserved.
+fn exchange_momentum(
+    a: &mut DynamicPhysicsBody,
+    a_offset: Vec3,
+    b: &mut DynamicPhysicsBody,
+    b_offset: Vec3,
+    normal: Vec3,
+) {
+    let relative_velocity = a.point_velocity(a_offset) - b.point_velocity(b_offset);
+    let impulse = contact_impulse(
+        relative_velocity,
+        normal,
+        a.base.material.combined_restitution(&b.base.material),
+        a.base.material.combined_friction(&b.base.material),
+        |direction| {
+            a.inverse_effective_mass(a_offset, direction)
+                + b.inverse_effective_mass(b_offset, direction)
+        },
+    );
+    a.apply_impulse(impulse, a_offset);
+    b.apply_impulse(-impulse, b_offset);
+}
+
+/// Like exchanging momentum with a body which cannot be moved, leaving all of the impulse to the
+/// dynamic body. The surface velocity is how fast the immovable body is moving at the contact
+/// point, which is how kinematic bodies push dynamic bodies around.
+fn bounce_off_immovable(
+    body: &mut DynamicPhysicsBody,
+    offset: Vec3,
+    surface_velocity: Vec3,
+    material: &PhysicsMaterial,
+    normal: Vec3,
+) {
+    let impulse = contact_impulse(
+        body.point_velocity(offset) - surface_velocity,
+        normal,
+        body.base.material.combined_restitution(material),
+        body.base.material.combined_friction(material),
+        |direction| body.inverse_effective_mass(offset, direction),
+    );
+    body.apply_impulse(impulse, offset);
+}
+
+/// Finds the impulse to apply at a contact in order to bounce the bodies apart along `normal` and
+/// to slow down how fast they slide along each other. The friction can at most bring the sliding
+/// to a halt, and is capped by how hard the bodies hit each other (Coulomb's law of friction).
+/// `inverse_mass` gives the combined inverse effective mass of the bodies in a direction.
+fn contact_impulse(
+    relative_velocity: Vec3,
+    normal: Vec3,
+    restitution: f32,
+    friction: f32,
+    inverse_mass: impl Fn(Vec3) -> f32,
+) -> Vec3 {
+    let approaching = relative_velocity.dot(normal);
+    if approaching >= 0.0 {
+        return Vec3::ZERO;
     }
-    let ua = ((b.1.x - b.0.x) * (a.0.y - b.0.y) - (b.1.y - b.0.y) * (a.0.x - b.1.x)) / denominator;
-    let ub = ((a.1.x - a.0.x) * (a.0.y - b.0.y) - (a.1.y - a.0.y) * (a.0.x - b.0.x)) / denominator;
-    if ua < 0.0 || ua > 1.0 || ub < 0.0 || ub > 1.0 {
-        false
-    } else {
-        true
+    let normal_impulse = -(1.0 + restitution) * approaching / inverse_mass(normal);
+    let sliding = relative_velocity - normal * approaching;
+    let sliding_speed = sliding.length();
+    if sliding_speed <= f32::EPSILON {
+        return normal * normal_impulse;
     }
+    let tangent = sliding / sliding_speed;
+    let friction_impulse = (sliding_speed / inverse_mass(tangent)).min(friction * normal_impulse);
+    normal * normal_impulse - tangent * friction_impulse
+}
+
+/// The contact point relative to the centre of a body, flattened onto the XZ-plane.
+fn contact_offset(contact: &Contact, transform: &Transform) -> Vec3 {
+    let mut offset = contact.point - transform.position;
+    offset.y = 0.0;
+    offset
+}
+
+/// The Y-component of the cross product between two vectors on the XZ-plane, which is all there is
+/// to the cross product when rotating around the Y-axis only.
+fn cross_y(a: Vec3, b: Vec3) -> f32 {
+    a.z * b.x - a.x * b.z
+}
+
+/// Checks whether the footprints of two colliders, each placed by their transform, overlap on the
+/// XZ-plane.
+pub fn is_overlapping(
+    a: &Transform,
+    a_collider: &Collider,
+    b: &Transform,
+    b_collider: &Collider,
+) -> bool {
+    intersect_shapes(&a_collider.polygons(a), &b_collider.polygons(b)).is_some()
 }
 
-fn get_bounding_lines(transform: &Transform, width: f32, depth: f32) -> [Line; 4] {
-    let matrix = Mat4::from_translation(transform.position)
-        * Mat4::from_scale(transform.scale)
-        * Mat4::from_rotation_y(radians(transform.rotation.y))
-        * Mat4::from_scale(Vec3::new(width, 1.0, depth));
-    let top_right = flatten_vec3(matrix.transform_point3(Vec3::new(0.5, 0.0, 0.5)));
-    let bottom_right = flatten_vec3(matrix.transform_point3(Vec3::new(0.5, 0.0, -0.5)));
-    let top_left = flatten_vec3(matrix.transform_point3(Vec3::new(-0.5, 0.0, 0.5)));
-    let bottom_left = flatten_vec3(matrix.transform_point3(Vec3::new(-0.5, 0.0, -0.5)));
-    [
-        Line(top_right, bottom_right),
-        Line(bottom_right, bottom_left),
-        Line(bottom_left, top_left),
-        Line(top_left, top_right),
-    ]
+/// Tests every polygon of one shape against every polygon of another, keeping the deepest contact
+/// since that is the one most in need of being resolved.
+fn intersect_shapes(a: &[Vec<Vec2>], b: &[Vec<Vec2>]) -> Option<Contact> {
+    a.iter()
+        .flat_map(|a| b.iter().filter_map(move |b| intersect_polygons(a, b)))
+        .max_by(|a, b| a.depth.total_cmp(&b.depth))
+}
+
+/// Tests two convex polygons on the XZ-plane for overlap using the separating axis theorem. The
+/// polygons overlap only if their projections overlap on every edge normal of both polygons, and
+/// the axis requiring the shortest push is the cheapest way of separating them.
+fn intersect_polygons(a: &[Vec2], b: &[Vec2]) -> Option<Contact> {
+    let mut normal = Vec2::ZERO;
+    let mut depth = f32::INFINITY;
+    let mut reference_is_a = true;
+    for (i, axis) in edge_normals(a).chain(edge_normals(b)).enumerate() {
+        let (a_min, a_max) = project(a, axis);
+        let (b_min, b_max) = project(b, axis);
+        // Measuring the push in both directions rather than the length of the shared interval
+        // gives the correct depth even when one of the polygons fully contains the other.
+        let backward = a_max - b_min;
+        let forward = b_max - a_min;
+        if backward <= 0.0 || forward <= 0.0 {
+            return None;
+        }
+        if backward < depth {
+            depth = backward;
+            normal = -axis;
+            reference_is_a = i < a.len();
+        }
+        if forward < depth {
+            depth = forward;
+            normal = axis;
+            reference_is_a = i < a.len();
+        }
+    }
+    // The polygon owning the separating edge is touched by the deepest corner of the other one.
+    // When two edges lie flush against each other both corners are equally deep, in which case the
+    // point between them is used.
+    let (incident, direction) = if reference_is_a {
+        (b, normal)
+    } else {
+        (a, -normal)
+    };
+    let deepest = project(incident, direction).1;
+    let corners: Vec<Vec2> = incident
+        .iter()
+        .copied()
+        .filter(|corner| deepest - corner.dot(direction) < 1e-3)
+        .collect();
+    let point = corners.iter().sum::<Vec2>() / corners.len() as f32;
+    Some(Contact {
+        normal: Vec3::new(normal.x, 0.0, normal.y),
+        depth,
+        point: Vec3::new(point.x, 0.0, point.y),
+    })
+}
+
+fn edge_normals(polygon: &[Vec2]) -> impl Iterator<Item = Vec2> + '_ {
+    (0..polygon.len()).map(move |i| {
+        let edge = polygon[(i + 1) % polygon.len()] - polygon[i];
+        edge.perp().normalize_or_zero()
+    })
+}
+
+fn project(polygon: &[Vec2], axis: Vec2) -> (f32, f32) {
+    polygon
+        .iter()
+        .map(|point| point.dot(axis))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), p| {
+            (min.min(p), max.max(p))
+        })
 }
 
 fn flatten_vec3(v: Vec3) -> Vec2 {
     Vec2::new(v.x, v.z)
 }
 
+/// The layer which bodies are placed on unless they have a reason not to be. Any other layers are
+/// up to the game to define, using any of the remaining bits.
+pub const DEFAULT_LAYER: u32 = 1;
+pub const ALL_LAYERS: u32 = u32::MAX;
+
+/// Two parties only interact if each of them is on a layer the other one collides with, which
+/// lets either of them opt out of the interaction.
+pub fn layers_interact(
+    a_layer: u32,
+    a_collides_with: u32,
+    b_layer: u32,
+    b_collides_with: u32,
+) -> bool {
+    a_layer & b_collides_with != 0 && b_layer & a_collides_with != 0
+}
+
 #[derive(Clone)]
 pub struct PhysicsBody {
     pub mass: f32,
-    pub width: f32,
-    pub depth: f32,
+    pub collider: Collider,
+    /// Bitmask of the layers the body belongs to, queries such as raycasts use it to pick which
+    /// bodies they are interested in.
+    pub layer: u32,
+    /// Bitmask of the layers the body collides with and triggers can be set off by.
+    pub collides_with: u32,
+    pub material: PhysicsMaterial,
+    /// How far the body reaches up from the position of its transform, scaled by the transform.
+    /// A body without a height reaches infinitely far both up and down, which is all that a flat
+    /// level needs.
+    pub height: Option<f32>,
+}
+
+impl PhysicsBody {
+    pub fn interacts_with(&self, other: &PhysicsBody) -> bool {
+        layers_interact(
+            self.layer,
+            self.collides_with,
+            other.layer,
+            other.collides_with,
+        )
+    }
 }
 
+/// A body which is moved by the forces acting on it. The force and torque carry the momentum of
+/// the body between frames, while the velocities are how far the body moved and turned (in
+/// radians) during the last frame.
 #[derive(Clone)]
 pub struct DynamicPhysicsBody {
     pub base: PhysicsBody,
     pub force: Vec3,
     pub velocity: Vec3,
+    pub torque: f32,
+    pub angular_velocity: f32,
+    pub inertia: f32,
+    /// Whether the body is standing on something, as opposed to flying through the air. Bodies
+    /// are always grounded unless there is gravity pulling them down.
+    pub grounded: bool,
+    /// How long the body has been at rest. A body which has been resting for long enough is put to
+    /// sleep, and is left alone by the velocity system until something pushes it.
+    pub rest_time: f32,
+}
+
+impl DynamicPhysicsBody {
+    /// The velocity, in units per second, of the point at `offset` from the centre of the body.
+    fn point_velocity(&self, offset: Vec3) -> Vec3 {
+        let angular = self.torque / self.inertia;
+        self.force / self.base.mass + Vec3::new(angular * offset.z, 0.0, -angular * offset.x)
+    }
+
+    /// How easily an impulse along `normal` at `offset` from the centre changes the velocity of
+    /// that point, combining how the body moves and turns.
+    fn inverse_effective_mass(&self, offset: Vec3, normal: Vec3) -> f32 {
+        1.0 / self.base.mass + cross_y(offset, normal).powi(2) / self.inertia
+    }
+
+    fn apply_impulse(&mut self, impulse: Vec3, offset: Vec3) {
+        if impulse != Vec3::ZERO {
+            self.wake();
+        }
+        self.force += impulse;
+        self.torque += cross_y(offset, impulse);
+    }
+
+    pub fn sleeping(&self) -> bool {
+        self.rest_time >= SLEEP_DELAY
+    }
+
+    pub fn wake(&mut self) {
+        self.rest_time = 0.0;
+    }
+}
+
+/// A body moved by gameplay rather than by forces, such as a moving barrier. It pushes dynamic
+/// bodies out of its way as if it had infinite mass, while nothing ever pushes back. Unlike for a
+/// dynamic body the velocities are set from the outside, in units and radians per second.
+#[derive(Clone)]
+pub struct KinematicBody {
+    pub base: PhysicsBody,
+    pub velocity: Vec3,
+    pub angular_velocity: f32,
+}
+
+impl KinematicBody {
+    /// The velocity, in units per second, of the point at `offset` from the centre of the body.
+    fn point_velocity(&self, offset: Vec3) -> Vec3 {
+        let angular = self.angular_velocity;
+        self.velocity + Vec3::new(angular * offset.z, 0.0, -angular * offset.x)
+    }
+}
+
+/// Moves kinematic bodies along by their velocities. Expected to run before the collision system,
+/// which then pushes any dynamic bodies out of their way.
+pub fn kinematic_system(ecs: &mut ECS, delta_time: f32) {
+    let bodies = ecs.query(
+        &Query::new()
+            .with::<Transform>()
+            .with::<KinematicBody>()
+            .build(),
+    );
+    for id in bodies {
+        let body = ecs.clone_component::<KinematicBody>(id).unwrap();
+        ecs.update_component::<Transform>(id, &mut |mut transform| {
+            transform.position += body.velocity * delta_time;
+            transform.rotation.y += (body.angular_velocity * delta_time).to_degrees();
+            transform
+        })
+        .unwrap();
+    }
+}
+
+/// The lowest and highest points of a body along the Y-axis.
+fn vertical_extent(transform: &Transform, body: &PhysicsBody) -> (f32, f32) {
+    match body.height {
+        Some(height) => (
+            transform.position.y,
+            transform.position.y + height * transform.scale.y,
+        ),
+        None => (f32::NEG_INFINITY, f32::INFINITY),
+    }
+}
+
+/// Bodies merely touching along the Y-axis, such as a body resting on top of another one, are not
+/// considered to overlap.
+fn overlaps_vertically(
+    a: &Transform,
+    a_body: &PhysicsBody,
+    b: &Transform,
+    b_body: &PhysicsBody,
+) -> bool {
+    let (a_bottom, a_top) = vertical_extent(a, a_body);
+    let (b_bottom, b_top) = vertical_extent(b, b_body);
+    a_bottom < b_top - VERTICAL_SKIN && b_bottom < a_top - VERTICAL_SKIN
+}
+
+/// The moment of inertia around the Y-axis of a solid box with the given size and mass.
+pub fn box_inertia(mass: f32, width: f32, depth: f32) -> f32 {
+    mass * (width * width + depth * depth) / 12.0
 }
 
 pub fn velocity_system(ecs: &mut ECS, delta_time: f32) {
@@ -126,18 +631,551 @@ pub fn velocity_system(ecs: &mut ECS, delta_time: f32) {
             .build(),
     );
     for id in bodies {
+        // Sleeping bodies have their momentum removed, hence any momentum they have picked up
+        // since means that something is pushing them.
+        let body = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
+        if body.sleeping() && body.force == Vec3::ZERO && body.torque == 0.0 {
+            continue;
+        }
         ecs.update_component::<DynamicPhysicsBody>(id, &mut |mut body| {
             body.velocity = (body.force / body.base.mass) * delta_time;
-            body.force -= body.velocity * 4.0;
+            // Drag only slows bodies down along the ground, gravity alone decides how they fall
+            body.force -= body.velocity * Vec3::new(1.0, 0.0, 1.0) * body.base.material.linear_drag;
+            body.angular_velocity = (body.torque / body.inertia) * delta_time;
+            body.torque -= body.angular_velocity * body.base.material.angular_drag;
+            let resting = (body.force / body.base.mass).length() < SLEEP_SPEED
+                && (body.torque / body.inertia).abs() < SLEEP_ANGULAR_SPEED;
+            body.rest_time = if resting {
+                body.rest_time + delta_time
+            } else {
+                0.0
+            };
+            if body.sleeping() {
+                body.force = Vec3::ZERO;
+                body.torque = 0.0;
+            }
             body
         })
         .unwrap();
 
         let body = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
+        let transform = ecs.clone_component::<Transform>(id).unwrap();
+        let polygons = body.base.collider.polygons(&transform);
+        let thickness = polygons
+            .iter()
+            .map(|polygon| thickness(polygon))
+            .fold(f32::INFINITY, f32::min);
+        // The collision system only ever sees where a body ends up, so a body moving further than
+        // half of its own thickness in a single frame could skip past a thin wall entirely. Those
+        // bodies are instead swept along their path and stopped at the first static body in it.
+        let horizontal = Vec3::new(body.velocity.x, 0.0, body.velocity.z);
+        let displacement = if horizontal.length() > thickness * CCD_THRESHOLD {
+            sweep_body(ecs, id, polygons, horizontal) + Vec3::Y * body.velocity.y
+        } else {
+            body.velocity
+        };
         ecs.update_component::<Transform>(id, &mut |mut transform| {
-            transform.position += body.velocity;
+            transform.position += displacement;
+            transform.rotation.y += body.angular_velocity.to_degrees();
             transform
         })
         .unwrap();
     }
 }
+
+/// The narrowest width of a convex polygon, measured across each of its edges.
+fn thickness(polygon: &[Vec2]) -> f32 {
+    edge_normals(polygon)
+        .map(|axis| {
+            let (min, max) = project(polygon, axis);
+            max - min
+        })
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Moves the polygons of a body along `displacement`, which is expected to lie on the XZ-plane,
+/// until they run into a static body, at which point the momentum heading into the static body is
+/// removed and the body continues sliding along its surface with whatever is left of the
+/// displacement. Returns how far the body could actually move.
+fn sweep_body(
+    ecs: &mut ECS,
+    id: EntityID,
+    mut polygons: Vec<Vec<Vec2>>,
+    displacement: Vec3,
+) -> Vec3 {
+    let body = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
+    let transform = ecs.clone_component::<Transform>(id).unwrap();
+    let mut moved = Vec3::ZERO;
+    let mut remaining = displacement;
+    for _ in 0..CCD_MAX_SWEEPS {
+        let distance = remaining.length();
+        if distance <= f32::EPSILON {
+            break;
+        }
+        let direction = flatten_vec3(remaining / distance);
+        let Some(broadphase) = ecs.get_resource::<Broadphase>() else {
+            return displacement;
+        };
+        let swept: Vec<Vec2> = polygons
+            .iter()
+            .flatten()
+            .flat_map(|corner| [*corner, *corner + direction * distance])
+            .collect();
+        let hit = broadphase
+            .statics
+            .query(&Aabb::from_points(&swept))
+            .into_iter()
+            .filter_map(|other| {
+                let other_body = ecs.clone_component::<PhysicsBody>(other).unwrap();
+                let other_transform = ecs.clone_component::<Transform>(other).unwrap();
+                if !body.base.interacts_with(&other_body)
+                    || !overlaps_vertically(&transform, &body.base, &other_transform, &other_body)
+                {
+                    return None;
+                }
+                let hits = polygons.iter().flat_map(|polygon| {
+                    broadphase
+                        .polygons(other)
+                        .iter()
+                        .filter_map(|target| sweep_polygon(polygon, direction, distance, target))
+                });
+                // A body already touching a surface has to be able to move away from it
+                hits.filter(|(_, normal)| normal.dot(direction) < 0.0)
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .map(|(time, normal)| (time, normal, other, other_body.material))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        let Some((time_of_impact, normal, other, material)) = hit else {
+            moved += remaining;
+            break;
+        };
+        let normal = Vec3::new(normal.x, 0.0, normal.y);
+        let step = remaining / distance * (time_of_impact - CCD_SKIN).max(0.0);
+        moved += step;
+        for corner in polygons.iter_mut().flatten() {
+            *corner += flatten_vec3(step);
+        }
+        remaining -= step;
+        remaining -= normal * remaining.dot(normal).min(0.0);
+        // The body is stopped before it ever overlaps the static body, so the collision system
+        // never gets to see the hit, not even on the next step. The corner leading into the static
+        // body is where it lands.
+        let normal_2d = flatten_vec3(normal);
+        let corner = polygons
+            .iter()
+            .flatten()
+            .min_by(|a, b| a.dot(normal_2d).total_cmp(&b.dot(normal_2d)))
+            .unwrap();
+        let contact = Contact {
+            normal,
+            depth: 0.0,
+            point: Vec3::new(corner.x, transform.position.y, corner.y),
+        };
+        let mut impact_speed = 0.0;
+        ecs.update_component::<DynamicPhysicsBody>(id, &mut |mut body| {
+            impact_speed = -body.point_velocity(Vec3::ZERO).dot(normal);
+            bounce_off_immovable(&mut body, Vec3::ZERO, Vec3::ZERO, &material, normal);
+            body
+        })
+        .unwrap();
+        if let Some(events) = ecs.get_resource_mut::<CollisionEvents>() {
+            events.record_swept(id, other, contact, impact_speed.max(0.0));
+        }
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::ECSBuilder;
+    use crate::core::physics::event::CollisionPhase;
+
+    fn square(x: f32, y: f32, size: f32) -> [Vec2; 4] {
+        let half = size / 2.0;
+        [
+            Vec2::new(x + half, y + half),
+            Vec2::new(x + half, y - half),
+            Vec2::new(x - half, y - half),
+            Vec2::new(x - half, y + half),
+        ]
+    }
+
+    #[test]
+    fn intersect_polygons_separated() {
+        assert_eq!(
+            intersect_polygons(&square(0.0, 0.0, 1.0), &square(2.0, 0.0, 1.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn intersect_polygons_contained() {
+        let contact = intersect_polygons(&square(0.0, 0.0, 1.0), &square(0.0, 0.0, 8.0)).unwrap();
+        assert_eq!(contact.depth, 4.5);
+    }
+
+    #[test]
+    fn intersect_polygons_normal_points_towards_first() {
+        let contact = intersect_polygons(&square(0.0, 0.0, 2.0), &square(1.5, 0.0, 2.0)).unwrap();
+        assert_eq!(contact.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(contact.depth, 0.5);
+    }
+
+    fn body(mass: f32, force: Vec3) -> DynamicPhysicsBody {
+        DynamicPhysicsBody {
+            base: PhysicsBody {
+                mass,
+                collider: Collider::rectangle(1.0, 1.0),
+                layer: DEFAULT_LAYER,
+                collides_with: ALL_LAYERS,
+                material: PhysicsMaterial::default(),
+                height: None,
+            },
+            force,
+            velocity: Vec3::default(),
+            torque: 0.0,
+            angular_velocity: 0.0,
+            inertia: box_inertia(mass, 1.0, 1.0),
+            grounded: true,
+            rest_time: 0.0,
+        }
+    }
+
+    fn spawn(ecs: &mut ECS, position: Vec3) -> EntityID {
+        let id = ecs.create_entity();
+        let transform = Transform {
+            position,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(id, transform).unwrap();
+        id
+    }
+
+    #[test]
+    fn velocity_system_stops_fast_bodies_at_thin_walls() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_resource(Broadphase::new(8.0))
+            .build();
+        let wall = spawn(&mut ecs, Vec3::new(5.0, 0.0, 0.0));
+        let mut wall_body = body(50.0, Vec3::ZERO).base;
+        wall_body.collider = Collider::rectangle(0.2, 8.0);
+        attach_static_body(&mut ecs, wall, wall_body).unwrap();
+        let car = spawn(&mut ecs, Vec3::ZERO);
+        ecs.attach_component(car, body(1.0, Vec3::new(600.0, 0.0, 0.0)))
+            .unwrap();
+
+        collision_system(&mut ecs);
+        velocity_system(&mut ecs, 1.0 / 60.0);
+        let position = ecs.clone_component::<Transform>(car).unwrap().position;
+        assert!(position.x < 4.4, "tunnelled to {}", position.x);
+        assert!(position.x > 4.0);
+    }
+
+    #[test]
+    fn layers_interact_requires_both_masks() {
+        assert!(layers_interact(0b01, ALL_LAYERS, 0b10, ALL_LAYERS));
+        assert!(!layers_interact(0b01, 0b01, 0b10, ALL_LAYERS));
+        assert!(!layers_interact(0b01, ALL_LAYERS, 0b10, 0b10));
+    }
+
+    #[test]
+    fn intersect_polygons_contact_point() {
+        let contact = intersect_polygons(&square(0.0, 0.0, 2.0), &square(1.5, 0.0, 2.0)).unwrap();
+        assert!(contact.point.abs_diff_eq(Vec3::new(0.5, 0.0, 0.0), 1e-6));
+    }
+
+    #[test]
+    fn exchange_momentum_conserves_momentum() {
+        let mut a = body(1.0, Vec3::new(-4.0, 0.0, 0.0));
+        let mut b = body(3.0, Vec3::new(2.0, 0.0, 1.0));
+        let total = a.force + b.force;
+        let normal = Vec3::new(1.0, 0.0, 0.0);
+        exchange_momentum(&mut a, Vec3::ZERO, &mut b, Vec3::ZERO, normal);
+        assert!((a.force + b.force).abs_diff_eq(total, 1e-6));
+        assert!(a.force.x > 0.0);
+    }
+
+    #[test]
+    fn exchange_momentum_is_symmetric() {
+        let (mut a, mut b) = (
+            body(1.0, Vec3::X * -4.0),
+            body(3.0, Vec3::new(2.0, 0.0, 1.0)),
+        );
+        let (mut a_swapped, mut b_swapped) = (a.clone(), b.clone());
+        let offset = Vec3::new(0.0, 0.0, 0.5);
+        exchange_momentum(&mut a, offset, &mut b, -offset, Vec3::X);
+        exchange_momentum(&mut b_swapped, -offset, &mut a_swapped, offset, -Vec3::X);
+        assert!(a.force.abs_diff_eq(a_swapped.force, 1e-6));
+        assert!(b.force.abs_diff_eq(b_swapped.force, 1e-6));
+        assert_eq!(a.torque, a_swapped.torque);
+        assert_eq!(b.torque, b_swapped.torque);
+    }
+
+    #[test]
+    fn exchange_momentum_off_centre_spins_bodies() {
+        let mut a = body(1.0, Vec3::X * -4.0);
+        let mut b = body(1.0, Vec3::ZERO);
+        let offset = Vec3::new(0.0, 0.0, 0.5);
+        exchange_momentum(&mut a, offset, &mut b, -offset, Vec3::X);
+        assert!(a.torque != 0.0);
+        assert_eq!(a.torque, b.torque);
+    }
+
+    #[test]
+    fn bounce_off_immovable_friction_cannot_reverse_sliding() {
+        let mut sliding = body(1.0, Vec3::new(-1.0, 0.0, 4.0));
+        sliding.base.material.friction = 1.0;
+        let wall = PhysicsMaterial {
+            friction: 1.0,
+            ..PhysicsMaterial::default()
+        };
+        bounce_off_immovable(&mut sliding, Vec3::ZERO, Vec3::ZERO, &wall, Vec3::X);
+        assert!(sliding.force.x > 0.0);
+        assert!(sliding.force.z > 0.0 && sliding.force.z < 4.0);
+
+        let mut icy = body(1.0, Vec3::new(-1.0, 0.0, 4.0));
+        icy.base.material = PhysicsMaterial::ice();
+        bounce_off_immovable(
+            &mut icy,
+            Vec3::ZERO,
+            Vec3::ZERO,
+            &PhysicsMaterial::ice(),
+            Vec3::X,
+        );
+        assert!(icy.force.z > sliding.force.z);
+    }
+
+    #[test]
+    fn resting_bodies_fall_asleep_until_pushed() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<DynamicPhysicsBody>()
+            .build();
+        let id = spawn(&mut ecs, Vec3::ZERO);
+        ecs.attach_component(id, body(1.0, Vec3::new(0.01, 0.0, 0.0)))
+            .unwrap();
+        for _ in 0..60 {
+            velocity_system(&mut ecs, 1.0 / 60.0);
+        }
+        let resting = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
+        assert!(resting.sleeping());
+        assert_eq!(resting.force, Vec3::ZERO);
+
+        ecs.update_component::<DynamicPhysicsBody>(id, &mut |mut body| {
+            body.apply_impulse(Vec3::new(10.0, 0.0, 0.0), Vec3::ZERO);
+            body
+        })
+        .unwrap();
+        velocity_system(&mut ecs, 1.0 / 60.0);
+        let pushed = ecs.clone_component::<DynamicPhysicsBody>(id).unwrap();
+        assert!(!pushed.sleeping());
+        assert!(ecs.clone_component::<Transform>(id).unwrap().position.x > 0.0);
+    }
+
+    #[test]
+    fn kinematic_bodies_push_dynamic_bodies() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_component::<KinematicBody>()
+            .with_resource(Broadphase::new(8.0))
+            .build();
+        let pusher = spawn(&mut ecs, Vec3::ZERO);
+        let kinematic = KinematicBody {
+            base: body(1.0, Vec3::ZERO).base,
+            velocity: Vec3::new(5.0, 0.0, 0.0),
+            angular_velocity: 0.0,
+        };
+        ecs.attach_component(pusher, kinematic).unwrap();
+        let pushed = spawn(&mut ecs, Vec3::new(1.05, 0.0, 0.0));
+        ecs.attach_component(pushed, body(1.0, Vec3::ZERO)).unwrap();
+
+        for _ in 0..6 {
+            kinematic_system(&mut ecs, 1.0 / 60.0);
+            collision_system(&mut ecs);
+            velocity_system(&mut ecs, 1.0 / 60.0);
+        }
+        let body = ecs.clone_component::<DynamicPhysicsBody>(pushed).unwrap();
+        assert!(body.force.x > 0.0);
+        assert!(ecs.clone_component::<Transform>(pushed).unwrap().position.x > 1.05);
+    }
+
+    fn phases(ecs: &mut ECS) -> Vec<CollisionPhase> {
+        let events = ecs.get_resource_mut::<CollisionEvents>().unwrap();
+        events.drain().iter().map(|event| event.phase).collect()
+    }
+
+    fn spawn_wall(ecs: &mut ECS, position: Vec3, width: f32, depth: f32) -> EntityID {
+        let wall = spawn(ecs, position);
+        let mut wall_body = body(50.0, Vec3::ZERO).base;
+        wall_body.collider = Collider::rectangle(width, depth);
+        attach_static_body(ecs, wall, wall_body).unwrap();
+        wall
+    }
+
+    #[test]
+    fn freed_static_bodies_leave_the_broadphase() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_resource(Broadphase::new(8.0))
+            .build();
+        let wall = spawn_wall(&mut ecs, Vec3::new(1.0, 0.0, 0.0), 1.0, 4.0);
+        let broadphase = ecs.get_resource::<Broadphase>().unwrap();
+        assert!(broadphase.statics.contains(wall));
+
+        free_body(&mut ecs, wall);
+        assert!(ecs.get_resource::<Broadphase>().unwrap().statics.is_empty());
+        let car = spawn(&mut ecs, Vec3::new(0.1, 0.0, 0.0));
+        ecs.attach_component(car, body(1.0, Vec3::ZERO)).unwrap();
+        collision_system(&mut ecs);
+        let position = ecs.clone_component::<Transform>(car).unwrap().position;
+        assert_eq!(position, Vec3::new(0.1, 0.0, 0.0));
+    }
+
+    #[test]
+    fn collision_system_reports_collision_phases() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_resource(Broadphase::new(8.0))
+            .with_resource(CollisionEvents::default())
+            .build();
+        let wall = spawn_wall(&mut ecs, Vec3::new(1.0, 0.0, 0.0), 1.0, 4.0);
+        let car = spawn(&mut ecs, Vec3::new(0.1, 0.0, 0.0));
+        ecs.attach_component(car, body(1.0, Vec3::new(3.0, 0.0, 0.0)))
+            .unwrap();
+
+        collision_system(&mut ecs);
+        let events = ecs.get_resource_mut::<CollisionEvents>().unwrap();
+        let hit = *events.began(car).next().unwrap();
+        assert_eq!((hit.a, hit.b), (car, wall));
+        assert_eq!(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(hit.impact_speed, 3.0);
+        assert!(events.touching(wall, car));
+        events.drain();
+
+        // Pushing the car back into the wall keeps the two touching
+        ecs.update_component::<Transform>(car, &mut |mut transform| {
+            transform.position.x = 0.2;
+            transform
+        })
+        .unwrap();
+        collision_system(&mut ecs);
+        assert_eq!(phases(&mut ecs), vec![CollisionPhase::Persist]);
+
+        ecs.update_component::<Transform>(car, &mut |mut transform| {
+            transform.position.x = -2.0;
+            transform
+        })
+        .unwrap();
+        collision_system(&mut ecs);
+        assert_eq!(phases(&mut ecs), vec![CollisionPhase::End]);
+        collision_system(&mut ecs);
+        assert!(phases(&mut ecs).is_empty());
+    }
+
+    #[test]
+    fn collision_events_pile_up_until_drained() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_resource(Broadphase::new(8.0))
+            .with_resource(CollisionEvents::default())
+            .build();
+        spawn_wall(&mut ecs, Vec3::new(1.0, 0.0, 0.0), 1.0, 4.0);
+        let car = spawn(&mut ecs, Vec3::new(0.1, 0.0, 0.0));
+        ecs.attach_component(car, body(1.0, Vec3::ZERO)).unwrap();
+
+        // Two steps taken in the same frame, with the car pushed back into the wall in between
+        collision_system(&mut ecs);
+        ecs.update_component::<Transform>(car, &mut |mut transform| {
+            transform.position.x = 0.2;
+            transform
+        })
+        .unwrap();
+        collision_system(&mut ecs);
+        assert_eq!(
+            phases(&mut ecs),
+            vec![CollisionPhase::Begin, CollisionPhase::Persist]
+        );
+        assert!(phases(&mut ecs).is_empty());
+    }
+
+    #[test]
+    fn swept_bodies_keep_touching_the_wall() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_resource(Broadphase::new(8.0))
+            .with_resource(CollisionEvents::default())
+            .build();
+        let wall = spawn_wall(&mut ecs, Vec3::new(5.0, 0.0, 0.0), 1.0, 8.0);
+        let car = spawn(&mut ecs, Vec3::ZERO);
+        ecs.attach_component(car, body(1.0, Vec3::ZERO)).unwrap();
+        let step = |ecs: &mut ECS, force: f32| {
+            ecs.update_component::<DynamicPhysicsBody>(car, &mut |mut body| {
+                body.force.x = force;
+                body
+            })
+            .unwrap();
+            collision_system(ecs);
+            velocity_system(ecs, 1.0 / 60.0);
+        };
+
+        // Floored into the wall, the car is stopped short of it by the sweep on every step
+        for _ in 0..4 {
+            step(&mut ecs, 600.0);
+        }
+        assert_eq!(
+            phases(&mut ecs),
+            vec![
+                CollisionPhase::Begin,
+                CollisionPhase::Persist,
+                CollisionPhase::Persist,
+                CollisionPhase::Persist
+            ]
+        );
+        assert!(ecs
+            .get_resource::<CollisionEvents>()
+            .unwrap()
+            .touching(car, wall));
+
+        for _ in 0..2 {
+            step(&mut ecs, -600.0);
+        }
+        assert_eq!(phases(&mut ecs).last(), Some(&CollisionPhase::End));
+    }
+
+    #[test]
+    fn collision_system_resolves_every_contact() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_resource(Broadphase::new(8.0))
+            .with_resource(CollisionEvents::default())
+            .build();
+        // A body wedged into a corner is pushed out of both walls at once
+        spawn_wall(&mut ecs, Vec3::new(1.0, 0.0, 0.0), 1.0, 4.0);
+        spawn_wall(&mut ecs, Vec3::new(0.0, 0.0, 1.0), 4.0, 1.0);
+        let car = spawn(&mut ecs, Vec3::new(0.1, 0.0, 0.2));
+        ecs.attach_component(car, body(1.0, Vec3::ZERO)).unwrap();
+
+        collision_system(&mut ecs);
+        let position = ecs.clone_component::<Transform>(car).unwrap().position;
+        assert!(position.x <= 1e-5 && position.z <= 1e-5);
+        let events = ecs.get_resource::<CollisionEvents>().unwrap();
+        assert_eq!(events.began(car).count(), 2);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::core::ecs::component::Transform;
+use crate::core::ecs::{EntityID, Query, ECS};
+use crate::core::physics::broadphase::{Aabb, Broadphase};
+use crate::core::physics::collider::Collider;
+use crate::core::physics::{
+    edge_normals, flatten_vec3, intersect_shapes, project, DynamicPhysicsBody, KinematicBody,
+    PhysicsBody, ALL_LAYERS,
+};
+use glam::{Vec2, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RaycastHit {
+    pub entity: EntityID,
+    pub distance: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+/// Decides which bodies a raycast or box cast may hit. Only bodies on a layer included in the mask
+/// are considered, and the excluded entity is always ignored, which is handy when casting from
+/// within a body.
+#[derive(Copy, Clone)]
+pub struct QueryFilter {
+    pub mask: u32,
+    pub exclude: Option<EntityID>,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self {
+            mask: ALL_LAYERS,
+            exclude: None,
+        }
+    }
+}
+
+impl QueryFilter {
+    pub fn with_mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn excluding(mut self, entity: EntityID) -> Self {
+        self.exclude = Some(entity);
+        self
+    }
+
+    fn accepts(&self, entity: EntityID, body: &PhysicsBody) -> bool {
+        self.exclude != Some(entity) && body.layer & self.mask != 0
+    }
+}
+
+/// Finds the first body hit by a ray travelling from `origin` along `direction`. Like the rest of
+/// the physics the ray lives on the XZ-plane, so any height of the origin and direction is ignored.
+pub fn raycast(
+    ecs: &ECS,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    filter: &QueryFilter,
+) -> Option<RaycastHit> {
+    let hit = cast(
+        ecs,
+        &[vec![flatten_vec3(origin)]],
+        direction,
+        max_distance,
+        filter,
+    )?;
+    let mut point = origin + flat_direction(direction) * hit.distance;
+    point.y = 0.0;
+    Some(RaycastHit { point, ..hit })
+}
+
+/// Sweeps a collider, placed by `transform` in the same way as for a physics body, along
+/// `direction` and finds the first body it would run into.
+pub fn box_cast(
+    ecs: &ECS,
+    transform: &Transform,
+    collider: &Collider,
+    direction: Vec3,
+    max_distance: f32,
+    filter: &QueryFilter,
+) -> Option<RaycastHit> {
+    let polygons = collider.polygons(transform);
+    let hit = cast(ecs, &polygons, direction, max_distance, filter)?;
+    // The contact point is found by nudging the collider just past the point of impact and
+    // looking at how it overlaps the body that was hit.
+    let offset = flatten_vec3(flat_direction(direction) * (hit.distance + 1e-3));
+    let moved: Vec<Vec<Vec2>> = polygons
+        .iter()
+        .map(|polygon| polygon.iter().map(|point| *point + offset).collect())
+        .collect();
+    let point = intersect_shapes(&moved, &body_polygons(ecs, hit.entity).unwrap())
+        .map(|contact| contact.point)
+        .unwrap_or(transform.position + flat_direction(direction) * hit.distance);
+    Some(RaycastHit { point, ..hit })
+}
+
+fn cast(
+    ecs: &ECS,
+    shape: &[Vec<Vec2>],
+    direction: Vec3,
+    max_distance: f32,
+    filter: &QueryFilter,
+) -> Option<RaycastHit> {
+    let direction = flatten_vec3(flat_direction(direction));
+    if direction == Vec2::ZERO {
+        return None;
+    }
+    let swept: Vec<Vec2> = shape
+        .iter()
+        .flatten()
+        .flat_map(|point| [*point, *point + direction * max_distance])
+        .collect();
+    let mut closest: Option<RaycastHit> = None;
+    for entity in candidates(ecs, &Aabb::from_points(&swept)) {
+        let Some(body) = physics_body(ecs, entity) else {
+            continue;
+        };
+        if !filter.accepts(entity, &body) {
+            continue;
+        }
+        let Some((distance, normal)) = body_polygons(ecs, entity)
+            .unwrap()
+            .iter()
+            .flat_map(|polygon| shape.iter().map(move |moving| (moving, polygon)))
+            .filter_map(|(moving, polygon)| sweep_polygon(moving, direction, max_distance, polygon))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+        else {
+            continue;
+        };
+        if closest.is_none_or(|closest| distance < closest.distance) {
+            closest = Some(RaycastHit {
+                entity,
+                distance,
+                point: Vec3::ZERO,
+                normal: Vec3::new(normal.x, 0.0, normal.y),
+            });
+        }
+    }
+    closest
+}
+
+/// Finds how far `moving` can travel along `direction` before touching `target`, along with the
+/// normal of the surface it touches. Both polygons are projected onto every separating axis, and
+/// the polygons only touch once they overlap on all axes at the same time. A polygon made up of a
+/// single point turns this into a raycast.
+pub(super) fn sweep_polygon(
+    moving: &[Vec2],
+    direction: Vec2,
+    max_distance: f32,
+    target: &[Vec2],
+) -> Option<(f32, Vec2)> {
+    let mut enter = f32::NEG_INFINITY;
+    let mut exit = f32::INFINITY;
+    let mut normal = -direction;
+    for axis in edge_normals(moving).chain(edge_normals(target)) {
+        if axis == Vec2::ZERO {
+            continue;
+        }
+        let (moving_min, moving_max) = project(moving, axis);
+        let (target_min, target_max) = project(target, axis);
+        let speed = direction.dot(axis);
+        if speed.abs() < f32::EPSILON {
+            if moving_max <= target_min || moving_min >= target_max {
+                return None;
+            }
+            continue;
+        }
+        let (axis_enter, axis_exit, axis_normal) = if speed > 0.0 {
+            (
+                (target_min - moving_max) / speed,
+                (target_max - moving_min) / speed,
+                -axis,
+            )
+        } else {
+            (
+                (target_max - moving_min) / speed,
+                (target_min - moving_max) / speed,
+                axis,
+            )
+        };
+        if axis_enter > enter {
+            enter = axis_enter;
+            normal = axis_normal;
+        }
+        exit = exit.min(axis_exit);
+    }
+    if enter > exit || exit < 0.0 || enter > max_distance {
+        return None;
+    }
+    // A cast starting out inside of a body hits it immediately
+    Some((enter.max(0.0), normal))
+}
+
+fn candidates(ecs: &ECS, bounds: &Aabb) -> Vec<EntityID> {
+    match ecs.get_resource::<Broadphase>() {
+        Some(broadphase) => {
+            let mut found = broadphase.statics.query(bounds);
+            found.extend(broadphase.dynamics.query(bounds));
+            found.extend(broadphase.kinematics.query(bounds));
+            found
+        }
+        None => {
+            let mut found = ecs.query(&Query::new().with::<PhysicsBody>().build());
+            found.extend(ecs.query(&Query::new().with::<DynamicPhysicsBody>().build()));
+            found.extend(ecs.query(&Query::new().with::<KinematicBody>().build()));
+            found
+        }
+    }
+}
+
+fn physics_body(ecs: &ECS, entity: EntityID) -> Option<PhysicsBody> {
+    ecs.clone_component::<PhysicsBody>(entity)
+        .or_else(|| {
+            ecs.clone_component::<DynamicPhysicsBody>(entity)
+                .map(|body| body.base)
+        })
+        .or_else(|| {
+            ecs.clone_component::<KinematicBody>(entity)
+                .map(|body| body.base)
+        })
+}
+
+fn body_polygons(ecs: &ECS, entity: EntityID) -> Option<Vec<Vec<Vec2>>> {
+    let body = physics_body(ecs, entity)?;
+    let transform = ecs.clone_component::<Transform>(entity)?;
+    Some(body.collider.polygons(&transform))
+}
+
+fn flat_direction(direction: Vec3) -> Vec3 {
+    Vec3::new(direction.x, 0.0, direction.z).normalize_or_zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::ECSBuilder;
+    use crate::core::physics::material::PhysicsMaterial;
+    use crate::core::physics::{box_inertia, DEFAULT_LAYER};
+
+    const TRAFFIC_LAYER: u32 = 1 << 2;
+
+    /// A car on the traffic layer sitting in front of a wall, both of them two units wide and
+    /// straight ahead of the origin along the X-axis.
+    fn world() -> (ECS, EntityID, EntityID) {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_component::<KinematicBody>()
+            .build();
+        let body = |layer| PhysicsBody {
+            mass: 1.0,
+            collider: Collider::rectangle(2.0, 2.0),
+            layer,
+            collides_with: ALL_LAYERS,
+            material: PhysicsMaterial::default(),
+            height: None,
+        };
+        let wall = spawn(&mut ecs, Vec3::new(10.0, 0.0, 0.0));
+        ecs.attach_component(wall, body(DEFAULT_LAYER)).unwrap();
+        let car = spawn(&mut ecs, Vec3::new(5.0, 0.0, 0.0));
+        let dynamic = DynamicPhysicsBody {
+            base: body(TRAFFIC_LAYER),
+            force: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            torque: 0.0,
+            angular_velocity: 0.0,
+            inertia: box_inertia(1.0, 2.0, 2.0),
+            grounded: true,
+            rest_time: 0.0,
+        };
+        ecs.attach_component(car, dynamic).unwrap();
+        (ecs, wall, car)
+    }
+
+    fn spawn(ecs: &mut ECS, position: Vec3) -> EntityID {
+        let id = ecs.create_entity();
+        let transform = Transform {
+            position,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(id, transform).unwrap();
+        id
+    }
+
+    #[test]
+    fn raycast_hits_the_nearest_body() {
+        let (ecs, _, car) = world();
+        let origin = Vec3::new(0.0, 3.0, 0.0);
+        let hit = raycast(&ecs, origin, Vec3::X, 20.0, &QueryFilter::default()).unwrap();
+        assert_eq!(hit.entity, car);
+        assert_eq!(hit.distance, 4.0);
+        assert_eq!(hit.point, Vec3::new(4.0, 0.0, 0.0));
+        assert_eq!(hit.normal, Vec3::NEG_X);
+        assert_eq!(
+            raycast(&ecs, origin, Vec3::X, 3.0, &QueryFilter::default()),
+            None
+        );
+        assert_eq!(
+            raycast(&ecs, origin, Vec3::Z, 20.0, &QueryFilter::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn raycast_skips_filtered_bodies() {
+        let (ecs, wall, car) = world();
+        let masked = QueryFilter::default().with_mask(DEFAULT_LAYER);
+        let hit = raycast(&ecs, Vec3::ZERO, Vec3::X, 20.0, &masked).unwrap();
+        assert_eq!((hit.entity, hit.distance), (wall, 9.0));
+        let excluded = QueryFilter::default().excluding(car);
+        let hit = raycast(&ecs, Vec3::ZERO, Vec3::X, 20.0, &excluded).unwrap();
+        assert_eq!((hit.entity, hit.distance), (wall, 9.0));
+        let nothing = QueryFilter::default().with_mask(1 << 5);
+        assert_eq!(raycast(&ecs, Vec3::ZERO, Vec3::X, 20.0, &nothing), None);
+    }
+
+    #[test]
+    fn box_cast_hits_the_nearest_body() {
+        let (ecs, wall, car) = world();
+        let transform = Transform {
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        let filter = QueryFilter::default();
+        let collider = Collider::rectangle(1.0, 1.0);
+        let hit = box_cast(&ecs, &transform, &collider, Vec3::X, 20.0, &filter).unwrap();
+        assert_eq!(hit.entity, car);
+        assert_eq!(hit.distance, 3.5);
+        assert_eq!(hit.normal, Vec3::NEG_X);
+        assert!((hit.point.x - 4.0).abs() < 1e-2);
+        assert!(hit.point.z.abs() <= 0.5);
+        let filter = QueryFilter::default().excluding(car);
+        let hit = box_cast(&ecs, &transform, &collider, Vec3::X, 20.0, &filter).unwrap();
+        assert_eq!((hit.entity, hit.distance), (wall, 8.5));
+        // Any shape of collider can be cast, such as the circle around a barrel
+        let barrel = Collider::circle(0.5);
+        let hit = box_cast(&ecs, &transform, &barrel, Vec3::X, 20.0, &filter).unwrap();
+        assert_eq!(hit.entity, wall);
+        assert!((hit.distance - 8.5).abs() < 1e-2);
+    }
+
+    fn square(x: f32, y: f32, size: f32) -> [Vec2; 4] {
+        let half = size / 2.0;
+        [
+            Vec2::new(x + half, y + half),
+            Vec2::new(x + half, y - half),
+            Vec2::new(x - half, y - half),
+            Vec2::new(x - half, y + half),
+        ]
+    }
+
+    #[test]
+    fn sweep_polygon_ray_hit() {
+        let (distance, normal) =
+            sweep_polygon(&[Vec2::ZERO], Vec2::X, 10.0, &square(5.0, 0.0, 2.0)).unwrap();
+        assert_eq!(distance, 4.0);
+        assert_eq!(normal, -Vec2::X);
+    }
+
+    #[test]
+    fn sweep_polygon_ray_miss() {
+        assert_eq!(
+            sweep_polygon(&[Vec2::ZERO], Vec2::X, 10.0, &square(5.0, 3.0, 2.0)),
+            None
+        );
+        assert_eq!(
+            sweep_polygon(&[Vec2::ZERO], Vec2::X, 3.0, &square(5.0, 0.0, 2.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn sweep_polygon_box_hit() {
+        let (distance, normal) = sweep_polygon(
+            &square(0.0, 0.0, 2.0),
+            Vec2::Y,
+            10.0,
+            &square(1.5, 5.0, 2.0),
+        )
+        .unwrap();
+        assert_eq!(distance, 3.0);
+        assert_eq!(normal, -Vec2::Y);
+    }
+}
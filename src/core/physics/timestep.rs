@@ -0,0 +1,238 @@
+use crate::core::ecs::component::Transform;
+use crate::core::ecs::{EntityID, Query, ECS};
+use crate::core::physics::{DynamicPhysicsBody, KinematicBody};
+
+/// The most steps taken in a single frame. A frame running longer than this many steps drops the
+/// remaining time rather than trying to catch up, which would only make the next frame slower.
+const MAX_STEPS_PER_FRAME: usize = 5;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Puts the physics into deterministic mode, where it advances in fixed steps no matter how long
+/// each frame takes. Together with the stable iteration order of the ECS, this makes two runs fed
+/// the same seed and the same input on every tick end up in bit-identical states. Worlds without
+/// this resource advance the physics by the frame time instead.
+pub struct FixedTimestep {
+    pub step: f32,
+    accumulator: f32,
+    tick: u64,
+    state_hash: u64,
+}
+
+impl FixedTimestep {
+    /// Steps the physics `rate` times per second.
+    pub fn new(rate: u32) -> Self {
+        Self {
+            step: 1.0 / rate as f32,
+            accumulator: 0.0,
+            tick: 0,
+            state_hash: FNV_OFFSET_BASIS,
+        }
+    }
+
+    /// How many steps have been taken so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The hash of the world as it was at the end of the latest step, see `state_hash`.
+    pub fn state_hash(&self) -> u64 {
+        self.state_hash
+    }
+
+    /// Adds the time of a frame and takes out as many whole steps as fit, leaving the rest for the
+    /// next frame.
+    fn advance(&mut self, frame_time: f32) -> usize {
+        self.accumulator += frame_time;
+        let steps = (self.accumulator / self.step) as usize;
+        self.accumulator -= steps as f32 * self.step;
+        if steps > MAX_STEPS_PER_FRAME {
+            self.accumulator = 0.0;
+        }
+        steps.min(MAX_STEPS_PER_FRAME)
+    }
+}
+
+/// Splits the time of a frame into the steps the physics should advance by. These are fixed steps
+/// in deterministic mode, in which case there may be none at all for a short frame, or otherwise
+/// the frame time itself.
+pub fn steps(ecs: &mut ECS, frame_time: f32) -> Vec<f32> {
+    match ecs.get_resource_mut::<FixedTimestep>() {
+        Some(timestep) => vec![timestep.step; timestep.advance(frame_time)],
+        None => vec![frame_time],
+    }
+}
+
+/// Counts a step as taken and hashes the resulting state of the world. Expected to run at the end
+/// of every step, and does nothing outside of deterministic mode.
+pub fn end_step(ecs: &mut ECS) {
+    let hash = state_hash(ecs);
+    if let Some(timestep) = ecs.get_resource_mut::<FixedTimestep>() {
+        timestep.tick += 1;
+        timestep.state_hash = hash;
+    }
+}
+
+/// Hashes the transforms and momentum of every moving body, which is enough to tell whether two
+/// runs of the physics have drifted apart. The hash works on the exact bits of every float and is
+/// the same on every platform, so it can be compared between machines as well.
+pub fn state_hash(ecs: &ECS) -> u64 {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash = (hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    };
+    let mut bodies: Vec<EntityID> = ecs.query(
+        &Query::new()
+            .with::<Transform>()
+            .with::<DynamicPhysicsBody>()
+            .build(),
+    );
+    bodies.extend(
+        ecs.query(
+            &Query::new()
+                .with::<Transform>()
+                .with::<KinematicBody>()
+                .build(),
+        ),
+    );
+    bodies.sort();
+    for id in bodies {
+        let transform = ecs.clone_component::<Transform>(id).unwrap();
+        write(&(id as u64).to_le_bytes());
+        for vector in [transform.position, transform.rotation, transform.scale] {
+            for component in vector.to_array() {
+                write(&component.to_bits().to_le_bytes());
+            }
+        }
+        if let Some(body) = ecs.clone_component::<DynamicPhysicsBody>(id) {
+            for component in body.force.to_array() {
+                write(&component.to_bits().to_le_bytes());
+            }
+            write(&body.torque.to_bits().to_le_bytes());
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::ECSBuilder;
+    use crate::core::physics::broadphase::Broadphase;
+    use crate::core::physics::collider::Collider;
+    use crate::core::physics::gravity::Gravity;
+    use crate::core::physics::material::PhysicsMaterial;
+    use crate::core::physics::trigger::TriggerEvents;
+    use crate::core::physics::{
+        attach_static_body, box_inertia, physics_system, PhysicsBody, ALL_LAYERS, DEFAULT_LAYER,
+    };
+    use glam::Vec3;
+
+    fn world() -> ECS {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_resource(Broadphase::new(8.0))
+            .with_resource(Gravity::default())
+            .with_resource(TriggerEvents::default())
+            .with_resource(FixedTimestep::new(60))
+            .build();
+        let wall = spawn(&mut ecs, Vec3::new(6.0, 0.0, 0.0));
+        let body = PhysicsBody {
+            mass: 50.0,
+            collider: Collider::rectangle(1.0, 10.0),
+            layer: DEFAULT_LAYER,
+            collides_with: ALL_LAYERS,
+            material: PhysicsMaterial::default(),
+            height: None,
+        };
+        attach_static_body(&mut ecs, wall, body).unwrap();
+        // A pile of bodies knocking into each other, spinning off of the wall and falling down
+        for i in 0..8 {
+            let id = spawn(
+                &mut ecs,
+                Vec3::new(i as f32 * 0.7, 1.0 + i as f32, i as f32 * 0.3),
+            );
+            let body = DynamicPhysicsBody {
+                base: PhysicsBody {
+                    mass: 1.0 + i as f32,
+                    collider: Collider::circle(0.5),
+                    layer: DEFAULT_LAYER,
+                    collides_with: ALL_LAYERS,
+                    material: PhysicsMaterial::default(),
+                    height: Some(1.0),
+                },
+                force: Vec3::new(30.0 - i as f32 * 7.0, 0.0, 3.0 * i as f32),
+                velocity: Vec3::ZERO,
+                torque: i as f32,
+                angular_velocity: 0.0,
+                inertia: box_inertia(1.0 + i as f32, 1.0, 1.0),
+                grounded: false,
+                rest_time: 0.0,
+            };
+            ecs.attach_component(id, body).unwrap();
+        }
+        ecs
+    }
+
+    fn spawn(ecs: &mut ECS, position: Vec3) -> EntityID {
+        let id = ecs.create_entity();
+        let transform = Transform {
+            position,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(id, transform).unwrap();
+        id
+    }
+
+    /// Runs the world through frames of the given lengths, returning the hash after every step.
+    fn run(frame_times: &[f32]) -> (ECS, Vec<u64>) {
+        let mut ecs = world();
+        let mut hashes = vec![];
+        for frame_time in frame_times {
+            for step in steps(&mut ecs, *frame_time) {
+                physics_system(&mut ecs, step);
+                end_step(&mut ecs);
+                hashes.push(ecs.get_resource::<FixedTimestep>().unwrap().state_hash());
+            }
+        }
+        (ecs, hashes)
+    }
+
+    #[test]
+    fn identical_runs_are_bit_identical() {
+        let frames: Vec<f32> = (0..180).map(|i| [0.016, 0.021, 0.013][i % 3]).collect();
+        let (first, first_hashes) = run(&frames);
+        let (second, second_hashes) = run(&frames);
+        assert_eq!(first_hashes, second_hashes);
+        assert_eq!(state_hash(&first), state_hash(&second));
+        for id in first.query(&Query::new().with::<Transform>().build()) {
+            let a = first.clone_component::<Transform>(id).unwrap();
+            let b = second.clone_component::<Transform>(id).unwrap();
+            assert_eq!(
+                a.position.to_array().map(f32::to_bits),
+                b.position.to_array().map(f32::to_bits)
+            );
+            assert_eq!(
+                a.rotation.to_array().map(f32::to_bits),
+                b.rotation.to_array().map(f32::to_bits)
+            );
+        }
+    }
+
+    #[test]
+    fn fixed_steps_do_not_depend_on_frame_times() {
+        let (even, even_hashes) = run(&[1.0 / 60.0; 120]);
+        let (uneven, uneven_hashes) = run(&[1.0 / 30.0; 60]);
+        assert_eq!(
+            even.get_resource::<FixedTimestep>().unwrap().tick(),
+            uneven.get_resource::<FixedTimestep>().unwrap().tick()
+        );
+        assert_eq!(even_hashes, uneven_hashes);
+    }
+}
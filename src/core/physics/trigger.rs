@@ -0,0 +1,278 @@
+use crate::core::ecs::component::Transform;
+use crate::core::ecs::{EntityID, Query, ECS};
+use crate::core::physics::collider::Collider;
+use crate::core::physics::{
+    is_overlapping, layers_interact, DynamicPhysicsBody, ALL_LAYERS, DEFAULT_LAYER,
+};
+use std::collections::HashSet;
+
+/// A volume which detects dynamic bodies passing through it without ever pushing them back. The
+/// width and depth are scaled by the transform in the same way as for a physics body.
+#[derive(Clone)]
+pub struct Trigger {
+    pub width: f32,
+    pub depth: f32,
+    pub layer: u32,
+    /// Bitmask of the layers of the bodies which may set off the trigger.
+    pub collides_with: u32,
+    overlapping: HashSet<EntityID>,
+}
+
+impl Trigger {
+    pub fn new(width: f32, depth: f32) -> Self {
+        Self {
+            width,
+            depth,
+            layer: DEFAULT_LAYER,
+            collides_with: ALL_LAYERS,
+            overlapping: HashSet::new(),
+        }
+    }
+
+    pub fn with_mask(mut self, collides_with: u32) -> Self {
+        self.collides_with = collides_with;
+        self
+    }
+
+    pub fn overlapping(&self) -> &HashSet<EntityID> {
+        &self.overlapping
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TriggerEvent {
+    Enter { trigger: EntityID, body: EntityID },
+    Stay { trigger: EntityID, body: EntityID },
+    Exit { trigger: EntityID, body: EntityID },
+}
+
+/// Events emitted by the trigger system during the current frame. The events are replaced every
+/// time the trigger system runs, so any system interested in them must run after it.
+#[derive(Default)]
+pub struct TriggerEvents(pub Vec<TriggerEvent>);
+
+impl TriggerEvents {
+    pub fn entered(&self, trigger: EntityID, body: EntityID) -> bool {
+        self.0.contains(&TriggerEvent::Enter { trigger, body })
+    }
+
+    pub fn exited(&self, trigger: EntityID, body: EntityID) -> bool {
+        self.0.contains(&TriggerEvent::Exit { trigger, body })
+    }
+}
+
+pub fn trigger_system(ecs: &mut ECS) {
+    let triggers = ecs.query(&Query::new().with::<Transform>().with::<Trigger>().build());
+    let bodies = ecs.query(
+        &Query::new()
+            .with::<Transform>()
+            .with::<DynamicPhysicsBody>()
+            .build(),
+    );
+    let mut events = vec![];
+    for id in triggers {
+        let transform = ecs.clone_component::<Transform>(id).unwrap();
+        let trigger = ecs.clone_component::<Trigger>(id).unwrap();
+        let volume = Collider::rectangle(trigger.width, trigger.depth);
+        let mut overlapping = HashSet::new();
+        for body_id in bodies.iter().copied() {
+            let body = ecs.clone_component::<DynamicPhysicsBody>(body_id).unwrap();
+            let interacts = layers_interact(
+                trigger.layer,
+                trigger.collides_with,
+                body.base.layer,
+                body.base.collides_with,
+            );
+            let inside = interacts
+                && is_overlapping(
+                    &ecs.clone_component::<Transform>(body_id).unwrap(),
+                    &body.base.collider,
+                    &transform,
+                    &volume,
+                );
+            if !inside {
+                continue;
+            }
+            overlapping.insert(body_id);
+            if trigger.overlapping.contains(&body_id) {
+                events.push(TriggerEvent::Stay {
+                    trigger: id,
+                    body: body_id,
+                });
+            } else {
+                events.push(TriggerEvent::Enter {
+                    trigger: id,
+                    body: body_id,
+                });
+            }
+        }
+        // Bodies which have been freed since the last frame are reported as exiting as well, any
+        // listener keeping track of who is inside of a trigger would otherwise be left hanging.
+        let mut exited: Vec<EntityID> = trigger
+            .overlapping
+            .difference(&overlapping)
+            .copied()
+            .collect();
+        exited.sort();
+        for body_id in exited {
+            events.push(TriggerEvent::Exit {
+                trigger: id,
+                body: body_id,
+            });
+        }
+        ecs.update_component::<Trigger>(id, &mut |mut trigger| {
+            trigger.overlapping = overlapping.clone();
+            trigger
+        })
+        .unwrap();
+    }
+    ecs.get_resource_mut::<TriggerEvents>().unwrap().0 = events;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::ECSBuilder;
+    use crate::core::physics::material::PhysicsMaterial;
+    use crate::core::physics::{box_inertia, PhysicsBody};
+    use glam::Vec3;
+
+    const PLAYER_LAYER: u32 = 1 << 1;
+    const TRAFFIC_LAYER: u32 = 1 << 2;
+
+    fn world() -> ECS {
+        ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_component::<Trigger>()
+            .with_resource(TriggerEvents::default())
+            .build()
+    }
+
+    fn spawn(ecs: &mut ECS, position: Vec3) -> EntityID {
+        let id = ecs.create_entity();
+        let transform = Transform {
+            position,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(id, transform).unwrap();
+        id
+    }
+
+    fn spawn_body(ecs: &mut ECS, position: Vec3, layer: u32) -> EntityID {
+        let id = spawn(ecs, position);
+        let body = DynamicPhysicsBody {
+            base: PhysicsBody {
+                mass: 1.0,
+                collider: Collider::rectangle(1.0, 1.0),
+                layer,
+                collides_with: ALL_LAYERS,
+                material: PhysicsMaterial::default(),
+                height: None,
+            },
+            force: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            torque: 0.0,
+            angular_velocity: 0.0,
+            inertia: box_inertia(1.0, 1.0, 1.0),
+            grounded: true,
+            rest_time: 0.0,
+        };
+        ecs.attach_component(id, body).unwrap();
+        id
+    }
+
+    fn move_to(ecs: &mut ECS, id: EntityID, position: Vec3) {
+        ecs.update_component::<Transform>(id, &mut |mut transform| {
+            transform.position = position;
+            transform
+        })
+        .unwrap();
+    }
+
+    fn events(ecs: &ECS) -> Vec<TriggerEvent> {
+        ecs.get_resource::<TriggerEvents>().unwrap().0.clone()
+    }
+
+    #[test]
+    fn body_enters_stays_and_exits() {
+        let mut ecs = world();
+        let trigger = spawn(&mut ecs, Vec3::ZERO);
+        ecs.attach_component(trigger, Trigger::new(2.0, 2.0))
+            .unwrap();
+        let body = spawn_body(&mut ecs, Vec3::new(0.5, 0.0, 0.0), DEFAULT_LAYER);
+
+        trigger_system(&mut ecs);
+        assert_eq!(events(&ecs), vec![TriggerEvent::Enter { trigger, body }]);
+        assert!(ecs
+            .get_resource::<TriggerEvents>()
+            .unwrap()
+            .entered(trigger, body));
+        trigger_system(&mut ecs);
+        assert_eq!(events(&ecs), vec![TriggerEvent::Stay { trigger, body }]);
+        move_to(&mut ecs, body, Vec3::new(10.0, 0.0, 0.0));
+        trigger_system(&mut ecs);
+        assert_eq!(events(&ecs), vec![TriggerEvent::Exit { trigger, body }]);
+        assert!(ecs
+            .get_resource::<TriggerEvents>()
+            .unwrap()
+            .exited(trigger, body));
+        let trigger = ecs.clone_component::<Trigger>(trigger).unwrap();
+        assert!(trigger.overlapping().is_empty());
+    }
+
+    #[test]
+    fn mask_leaves_out_other_layers() {
+        let mut ecs = world();
+        let trigger = spawn(&mut ecs, Vec3::ZERO);
+        ecs.attach_component(trigger, Trigger::new(2.0, 2.0).with_mask(PLAYER_LAYER))
+            .unwrap();
+        spawn_body(&mut ecs, Vec3::ZERO, TRAFFIC_LAYER);
+        let player = spawn_body(&mut ecs, Vec3::ZERO, PLAYER_LAYER);
+
+        trigger_system(&mut ecs);
+        assert_eq!(
+            events(&ecs),
+            vec![TriggerEvent::Enter {
+                trigger,
+                body: player
+            }]
+        );
+    }
+
+    #[test]
+    fn freed_body_exits() {
+        let mut ecs = world();
+        let trigger = spawn(&mut ecs, Vec3::ZERO);
+        ecs.attach_component(trigger, Trigger::new(2.0, 2.0))
+            .unwrap();
+        let body = spawn_body(&mut ecs, Vec3::new(0.5, 0.0, 0.0), DEFAULT_LAYER);
+
+        trigger_system(&mut ecs);
+        ecs.free_entity(body);
+        trigger_system(&mut ecs);
+        assert_eq!(events(&ecs), vec![TriggerEvent::Exit { trigger, body }]);
+        trigger_system(&mut ecs);
+        assert!(events(&ecs).is_empty());
+    }
+
+    #[test]
+    fn events_are_replaced_every_run() {
+        let mut ecs = world();
+        let trigger = spawn(&mut ecs, Vec3::ZERO);
+        ecs.attach_component(trigger, Trigger::new(2.0, 2.0))
+            .unwrap();
+        let body = spawn_body(&mut ecs, Vec3::new(0.5, 0.0, 0.0), DEFAULT_LAYER);
+        ecs.get_resource_mut::<TriggerEvents>()
+            .unwrap()
+            .0
+            .push(TriggerEvent::Exit { trigger, body });
+
+        for _ in 0..3 {
+            trigger_system(&mut ecs);
+            assert_eq!(events(&ecs).len(), 1);
+        }
+        assert_eq!(events(&ecs), vec![TriggerEvent::Stay { trigger, body }]);
+    }
+}
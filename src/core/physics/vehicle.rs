@@ -0,0 +1,386 @@
+use crate::core::ecs::component::Transform;
+use crate::core::ecs::{Query, ECS};
+use crate::core::physics::material::{CombineRule, PhysicsMaterial};
+use crate::core::physics::DynamicPhysicsBody;
+use glam::Vec3;
+
+/// What the driver of a vehicle is asking of it. Throttle and brake range from 0 to 1 while
+/// steering ranges from -1 (right) to 1 (left).
+#[derive(Copy, Clone, Default)]
+pub struct VehicleInput {
+    pub throttle: f32,
+    pub brake: f32,
+    pub steer: f32,
+    pub handbrake: bool,
+}
+
+/// Describes how a vehicle handles. All forces are applied to the dynamic physics body of the same
+/// entity, which means that the mass of the body decides how sluggish the vehicle feels.
+#[derive(Clone)]
+pub struct Vehicle {
+    /// Engine force at evenly spaced speeds, from standing still up until the top speed. No
+    /// engine force is applied at or beyond the top speed.
+    pub engine_curve: Vec<f32>,
+    pub top_speed: f32,
+    pub brake_force: f32,
+    pub reverse_force: f32,
+    pub top_reverse_speed: f32,
+    /// Force slowing down the vehicle while neither accelerating nor braking.
+    pub rolling_resistance: f32,
+    /// How much of the sideways momentum is removed per second. A high grip keeps the vehicle
+    /// going where it is pointed, a low grip makes it slide.
+    pub grip: f32,
+    /// The grip used once the vehicle is sliding faster sideways than `drift_threshold`, or when
+    /// the handbrake is pulled.
+    pub drift_grip: f32,
+    pub drift_threshold: f32,
+    pub steering_torque: f32,
+    /// The speed at which the steering reaches full strength, a vehicle cannot turn on the spot.
+    pub full_steering_speed: f32,
+    /// How much of the steering strength is lost at top speed, from 0 (none) to 1 (all).
+    pub steering_falloff: f32,
+    /// How quickly the vehicle stops turning when the steering is released.
+    pub steering_return: f32,
+    /// The material of the tires, combined with the surface being driven over into the traction.
+    pub tires: PhysicsMaterial,
+    /// Scales the grip of the vehicle by how well the tires hold on to the surface below them,
+    /// kept up to date by the surface system.
+    pub traction: f32,
+    pub input: VehicleInput,
+}
+
+impl Default for Vehicle {
+    fn default() -> Self {
+        Self {
+            engine_curve: vec![160.0, 180.0, 170.0, 140.0, 90.0],
+            top_speed: 32.0,
+            brake_force: 240.0,
+            reverse_force: 80.0,
+            top_reverse_speed: 8.0,
+            rolling_resistance: 20.0,
+            grip: 12.0,
+            drift_grip: 2.0,
+            drift_threshold: 6.0,
+            steering_torque: 8.0,
+            full_steering_speed: 6.0,
+            steering_falloff: 0.5,
+            steering_return: 8.0,
+            tires: PhysicsMaterial {
+                friction: 1.0,
+                friction_combine: CombineRule::Multiply,
+                ..PhysicsMaterial::default()
+            },
+            traction: 1.0,
+            input: VehicleInput::default(),
+        }
+    }
+}
+
+impl Vehicle {
+    /// Interpolates the engine curve at `speed`, which is expected to be positive.
+    pub fn engine_force(&self, speed: f32) -> f32 {
+        if self.engine_curve.is_empty() || speed >= self.top_speed {
+            return 0.0;
+        }
+        let steps = (self.engine_curve.len() - 1).max(1) as f32;
+        let position = (speed / self.top_speed).max(0.0) * steps;
+        let index = position.floor() as usize;
+        let next = (index + 1).min(self.engine_curve.len() - 1);
+        let t = position - index as f32;
+        self.engine_curve[index] * (1.0 - t) + self.engine_curve[next] * t
+    }
+
+    /// The share of the steering torque available at `speed`.
+    fn steering_factor(&self, speed: f32) -> f32 {
+        let speed = speed.abs();
+        let ramp = (speed / self.full_steering_speed).min(1.0);
+        let falloff = 1.0 - self.steering_falloff * (speed / self.top_speed).min(1.0);
+        ramp * falloff
+    }
+}
+
+pub fn vehicle_system(ecs: &mut ECS, delta_time: f32) {
+    let vehicles = ecs.query(
+        &Query::new()
+            .with::<Vehicle>()
+            .with::<Transform>()
+            .with::<DynamicPhysicsBody>()
+            .build(),
+    );
+    for id in vehicles {
+        // A vehicle flying through the air has nothing to push against
+        if !ecs
+            .clone_component::<DynamicPhysicsBody>(id)
+            .unwrap()
+            .grounded
+        {
+            continue;
+        }
+        let vehicle = ecs.clone_component::<Vehicle>(id).unwrap();
+        let transform = ecs.clone_component::<Transform>(id).unwrap();
+        ecs.update_component::<DynamicPhysicsBody>(id, &mut |mut body| {
+            drive(&vehicle, &transform, &mut body, delta_time);
+            body
+        })
+        .unwrap();
+    }
+}
+
+fn drive(vehicle: &Vehicle, transform: &Transform, body: &mut DynamicPhysicsBody, delta_time: f32) {
+    let input = vehicle.input;
+    // Any input from the driver gets a parked vehicle going again
+    if input.throttle > 0.0 || input.brake > 0.0 || input.steer != 0.0 {
+        body.wake();
+    }
+    let mut forward = transform.forward();
+    forward.y = 0.0;
+    let forward = forward.normalize_or_zero();
+    // Climbing or coming down a ramp is up to the gravity, only the momentum along the ground is
+    // steered and held on to by the tires.
+    let velocity = body.force / body.base.mass;
+    let velocity = Vec3::new(velocity.x, 0.0, velocity.z);
+    let speed = velocity.dot(forward);
+    let lateral = velocity - forward * speed;
+
+    // Braking can never do more than bringing the vehicle to a stop, it is up to the reverse gear
+    // to get it going backwards.
+    let brake = |force: f32| -> Vec3 {
+        let stopping = speed.abs() * body.base.mass;
+        -forward * speed.signum() * (force * delta_time).min(stopping)
+    };
+    let mut force = Vec3::ZERO;
+    if input.throttle > 0.0 && speed < -0.5 {
+        force += brake(vehicle.brake_force * input.throttle);
+    } else if input.throttle > 0.0 {
+        force += forward * vehicle.engine_force(speed) * input.throttle * delta_time;
+    }
+    if input.brake > 0.0 && speed > 0.5 {
+        force += brake(vehicle.brake_force * input.brake);
+    } else if input.brake > 0.0 && speed > -vehicle.top_reverse_speed {
+        force -= forward * vehicle.reverse_force * input.brake * delta_time;
+    }
+    if input.throttle == 0.0 && input.brake == 0.0 {
+        force += brake(vehicle.rolling_resistance);
+    }
+
+    let grip = if input.handbrake || lateral.length() > vehicle.drift_threshold {
+        vehicle.drift_grip
+    } else {
+        vehicle.grip
+    } * vehicle.traction;
+    force -= lateral * body.base.mass * (grip * delta_time).min(1.0);
+    body.force += force;
+
+    // Steering is reversed when backing up, just like in a real car
+    if input.steer == 0.0 {
+        body.torque -= body.torque * (vehicle.steering_return * delta_time).min(1.0);
+    } else {
+        body.torque += vehicle.steering_torque
+            * vehicle.steering_factor(speed)
+            * speed.signum()
+            * input.steer
+            * delta_time;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::ECSBuilder;
+    use crate::core::physics::collider::Collider;
+    use crate::core::physics::gravity::{gravity_system, Gravity, Ground};
+    use crate::core::physics::{
+        box_inertia, velocity_system, PhysicsBody, ALL_LAYERS, DEFAULT_LAYER,
+    };
+
+    const STEP: f32 = 1.0 / 60.0;
+
+    /// A body of ten units of mass, moving at `velocity` while facing along the Z-axis.
+    fn body(velocity: Vec3) -> DynamicPhysicsBody {
+        DynamicPhysicsBody {
+            base: PhysicsBody {
+                mass: 10.0,
+                collider: Collider::rectangle(1.0, 2.0),
+                layer: DEFAULT_LAYER,
+                collides_with: ALL_LAYERS,
+                material: PhysicsMaterial::default(),
+                height: None,
+            },
+            force: velocity * 10.0,
+            velocity: Vec3::ZERO,
+            torque: 0.0,
+            angular_velocity: 0.0,
+            inertia: box_inertia(10.0, 1.0, 2.0),
+            grounded: true,
+            rest_time: 0.0,
+        }
+    }
+
+    fn drive_for(vehicle: &Vehicle, body: &mut DynamicPhysicsBody, steps: usize) {
+        let transform = Transform {
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        for _ in 0..steps {
+            drive(vehicle, &transform, body, STEP);
+        }
+    }
+
+    fn with_input(input: VehicleInput) -> Vehicle {
+        Vehicle {
+            input,
+            ..Vehicle::default()
+        }
+    }
+
+    #[test]
+    fn engine_force_interpolates_curve() {
+        let vehicle = Vehicle {
+            engine_curve: vec![100.0, 200.0, 50.0],
+            top_speed: 10.0,
+            ..Vehicle::default()
+        };
+        assert_eq!(vehicle.engine_force(0.0), 100.0);
+        assert_eq!(vehicle.engine_force(2.5), 150.0);
+        assert_eq!(vehicle.engine_force(5.0), 200.0);
+        assert_eq!(vehicle.engine_force(10.0), 0.0);
+    }
+
+    #[test]
+    fn steering_factor_ramps_up_and_falls_off() {
+        let vehicle = Vehicle::default();
+        assert_eq!(vehicle.steering_factor(0.0), 0.0);
+        assert!(vehicle.steering_factor(vehicle.full_steering_speed) > 0.5);
+        assert_eq!(vehicle.steering_factor(vehicle.top_speed), 0.5);
+    }
+
+    #[test]
+    fn braking_stops_without_reversing() {
+        let vehicle = Vehicle {
+            brake_force: 2000.0,
+            ..with_input(VehicleInput {
+                brake: 1.0,
+                ..VehicleInput::default()
+            })
+        };
+        let mut car = body(Vec3::new(0.0, 0.0, 2.0));
+        drive_for(&vehicle, &mut car, 1);
+        assert_eq!(car.force, Vec3::ZERO);
+
+        // Rolling to a stop with no input at all stops there as well
+        let mut car = body(Vec3::new(0.0, 0.0, 1.0));
+        drive_for(&Vehicle::default(), &mut car, 60);
+        assert_eq!(car.force, Vec3::ZERO);
+    }
+
+    #[test]
+    fn throttle_brakes_a_reversing_vehicle() {
+        let vehicle = Vehicle {
+            brake_force: 2000.0,
+            ..with_input(VehicleInput {
+                throttle: 1.0,
+                ..VehicleInput::default()
+            })
+        };
+        let mut car = body(Vec3::new(0.0, 0.0, -2.0));
+        drive_for(&vehicle, &mut car, 1);
+        assert_eq!(car.force, Vec3::ZERO);
+    }
+
+    #[test]
+    fn reverse_speed_is_capped() {
+        let vehicle = with_input(VehicleInput {
+            brake: 1.0,
+            ..VehicleInput::default()
+        });
+        let mut car = body(Vec3::ZERO);
+        drive_for(&vehicle, &mut car, 600);
+        let speed = car.force.z / car.base.mass;
+        // The last push may take it past the cap by a single step worth of reverse force
+        let step = vehicle.reverse_force * STEP / car.base.mass;
+        assert!(speed <= -vehicle.top_reverse_speed);
+        assert!(speed > -vehicle.top_reverse_speed - step);
+    }
+
+    #[test]
+    fn sliding_loses_grip() {
+        let vehicle = Vehicle::default();
+        let slide = |vehicle: &Vehicle, sideways: f32| {
+            let mut car = body(Vec3::new(sideways, 0.0, 0.0));
+            drive_for(vehicle, &mut car, 1);
+            car.force.x / car.base.mass
+        };
+        let grip = 1.0 - vehicle.grip * STEP;
+        let drift_grip = 1.0 - vehicle.drift_grip * STEP;
+        assert!((slide(&vehicle, 3.0) - 3.0 * grip).abs() < 1e-4);
+        assert!((slide(&vehicle, 8.0) - 8.0 * drift_grip).abs() < 1e-4);
+        let handbrake = with_input(VehicleInput {
+            handbrake: true,
+            ..VehicleInput::default()
+        });
+        assert!((slide(&handbrake, 3.0) - 3.0 * drift_grip).abs() < 1e-4);
+    }
+
+    #[test]
+    fn steering_flips_when_reversing() {
+        let vehicle = with_input(VehicleInput {
+            steer: 1.0,
+            ..VehicleInput::default()
+        });
+        let mut forwards = body(Vec3::new(0.0, 0.0, 5.0));
+        drive_for(&vehicle, &mut forwards, 1);
+        let mut backwards = body(Vec3::new(0.0, 0.0, -5.0));
+        drive_for(&vehicle, &mut backwards, 1);
+        assert!(forwards.torque > 0.0);
+        assert_eq!(backwards.torque, -forwards.torque);
+    }
+
+    #[test]
+    fn climbing_a_ramp_keeps_the_grip() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PhysicsBody>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_component::<Ground>()
+            .with_component::<Vehicle>()
+            .with_resource(Gravity::default())
+            .build();
+        let ramp = ecs.create_entity();
+        let transform = Transform {
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(ramp, transform).unwrap();
+        ecs.attach_component(ramp, Ground::ramp(4.0, 4.0, 2.0))
+            .unwrap();
+        let car = ecs.create_entity();
+        let transform = Transform {
+            position: Vec3::new(0.0, 0.0, -1.9),
+            ..transform
+        };
+        ecs.attach_component(car, transform).unwrap();
+        ecs.attach_component(car, body(Vec3::new(1.0, 0.0, 16.0)))
+            .unwrap();
+        ecs.attach_component(car, Vehicle::default()).unwrap();
+
+        // Half way up the ramp the car is climbing faster than it would need to be sliding sideways
+        // to lose its grip, which must not count towards the slide.
+        let steps = 6;
+        for _ in 0..steps {
+            vehicle_system(&mut ecs, STEP);
+            velocity_system(&mut ecs, STEP);
+            gravity_system(&mut ecs, STEP);
+        }
+        let car = ecs.clone_component::<DynamicPhysicsBody>(car).unwrap();
+        let velocity = car.force / car.base.mass;
+        let grip = 1.0 - Vehicle::default().grip * STEP;
+        assert!(velocity.y > Vehicle::default().drift_threshold);
+        assert!(
+            (velocity.x - grip.powi(steps)).abs() < 0.02,
+            "{}",
+            velocity.x
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use crate::core::render::model::Bounds;
+use glam::{Mat4, Vec3, Vec4};
+
+/// How far away from the camera models are still drawn. Anything further away is culled even when
+/// it is within the frustum of the camera. Worlds without this resource draw everything within the
+/// far plane of the lens.
+#[derive(Copy, Clone)]
+pub struct DrawDistance(pub f32);
+
+/// How many models were drawn and how many were culled during the latest frame.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+/// A sphere enclosing a model once placed in the world, which is cheaper to test against the
+/// frustum than the box it is built from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn from_bounds(bounds: &Bounds, model_matrix: &Mat4) -> Self {
+        let (scale, _, _) = model_matrix.to_scale_rotation_translation();
+        Self {
+            center: model_matrix.transform_point3(bounds.center()),
+            radius: (bounds.size() / 2.0).length() * scale.abs().max_element(),
+        }
+    }
+}
+
+/// The volume seen by the camera, made up of six planes with their normals pointing inwards.
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from the combined projection and view matrix, following Gribb and
+    /// Hartmann.
+    pub fn new(projection_matrix: &Mat4, view_matrix: &Mat4) -> Self {
+        let clip = *projection_matrix * *view_matrix;
+        let (x, y, z, w) = (clip.row(0), clip.row(1), clip.row(2), clip.row(3));
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z]
+            .map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+
+    pub fn contains(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+}
+
+/// Decides whether a model is worth drawing, being within both the frustum and the draw distance
+/// as seen from `eye`.
+pub fn is_visible(
+    frustum: &Frustum,
+    eye: Vec3,
+    draw_distance: Option<f32>,
+    sphere: &BoundingSphere,
+) -> bool {
+    let within_distance = draw_distance
+        .is_none_or(|distance| eye.distance(sphere.center) - sphere.radius <= distance);
+    within_distance && frustum.contains(sphere)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frustum() -> Frustum {
+        let projection = Mat4::perspective_rh(45f32.to_radians(), 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        Frustum::new(&projection, &view)
+    }
+
+    fn sphere(x: f32, y: f32, z: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: Vec3::new(x, y, z),
+            radius: 1.0,
+        }
+    }
+
+    #[test]
+    fn frustum_culls_what_the_camera_cannot_see() {
+        let frustum = frustum();
+        assert!(frustum.contains(&sphere(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains(&sphere(0.0, 0.0, 10.0)));
+        assert!(!frustum.contains(&sphere(20.0, 0.0, -10.0)));
+        assert!(!frustum.contains(&sphere(0.0, 0.0, -120.0)));
+        // Spheres poking into the frustum are still drawn
+        assert!(frustum.contains(&sphere(4.9, 0.0, -10.0)));
+    }
+
+    #[test]
+    fn draw_distance_culls_far_away_models() {
+        let frustum = frustum();
+        let far = sphere(0.0, 0.0, -50.0);
+        assert!(is_visible(&frustum, Vec3::ZERO, None, &far));
+        assert!(!is_visible(&frustum, Vec3::ZERO, Some(40.0), &far));
+        assert!(is_visible(&frustum, Vec3::ZERO, Some(49.5), &far));
+    }
+
+    #[test]
+    fn bounding_sphere_follows_transform() {
+        let bounds = Bounds {
+            min: Vec3::new(-1.0, 0.0, -1.0),
+            max: Vec3::new(1.0, 2.0, 1.0),
+        };
+        let matrix =
+            Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0)) * Mat4::from_scale(Vec3::splat(2.0));
+        let sphere = BoundingSphere::from_bounds(&bounds, &matrix);
+        assert!(sphere.center.abs_diff_eq(Vec3::new(5.0, 2.0, 0.0), 1e-5));
+        assert!((sphere.radius - 3f32.sqrt() * 2.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::core::render::Color;
+
+/// How much of the light reaching the camera is left at the distance an exponential fog is
+/// considered to hide everything, which is less than a single step of an 8-bit colour channel.
+const EXPONENTIAL_FOG_CUTOFF: f32 = 1.0 / 256.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FogFalloff {
+    /// Thickens evenly from `start` until it hides everything at `end`.
+    Linear { start: f32, end: f32 },
+    /// Thickens quickly close to the camera and ever more slowly further away, the way real fog
+    /// does.
+    Exponential { density: f32 },
+}
+
+/// Fades everything out into a single colour the further away it is from the camera, which is
+/// also the colour the screen is cleared with. Models are culled where the fog hides everything,
+/// so that they fade in rather than pop into view. Worlds without this resource are drawn without
+/// any fog.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fog {
+    pub color: Color,
+    pub falloff: FogFalloff,
+}
+
+impl Fog {
+    pub fn linear(color: Color, start: f32, end: f32) -> Self {
+        Self {
+            color,
+            falloff: FogFalloff::Linear { start, end },
+        }
+    }
+
+    pub fn exponential(color: Color, density: f32) -> Self {
+        Self {
+            color,
+            falloff: FogFalloff::Exponential { density },
+        }
+    }
+
+    /// How much of something `distance` units away from the camera is hidden by the fog, from zero
+    /// for not at all to one for completely. This is worked out the same way as in the fragment
+    /// shader, which has to be kept in step with it.
+    pub fn amount(&self, distance: f32) -> f32 {
+        match self.falloff {
+            FogFalloff::Linear { start, end } => {
+                ((distance - start) / (end - start).max(0.0001)).clamp(0.0, 1.0)
+            }
+            FogFalloff::Exponential { density } => 1.0 - (-density * distance.max(0.0)).exp(),
+        }
+    }
+
+    /// How far away from the camera the fog hides everything.
+    pub fn end(&self) -> f32 {
+        match self.falloff {
+            FogFalloff::Linear { end, .. } => end,
+            FogFalloff::Exponential { density } => {
+                -EXPONENTIAL_FOG_CUTOFF.ln() / density.max(f32::EPSILON)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Color = Color(0.0, 0.0, 0.0, 1.0);
+
+    #[test]
+    fn linear_fog_thickens_between_start_and_end() {
+        let fog = Fog::linear(BLACK, 20.0, 60.0);
+        assert_eq!(fog.amount(10.0), 0.0);
+        assert_eq!(fog.amount(40.0), 0.5);
+        assert_eq!(fog.amount(fog.end()), 1.0);
+        assert_eq!(fog.amount(80.0), 1.0);
+        assert_eq!(fog.end(), 60.0);
+    }
+
+    #[test]
+    fn linear_fog_without_depth_is_a_wall() {
+        let fog = Fog::linear(BLACK, 30.0, 30.0);
+        assert_eq!(fog.amount(29.0), 0.0);
+        assert_eq!(fog.amount(31.0), 1.0);
+    }
+
+    #[test]
+    fn exponential_fog_ends_where_too_little_light_is_left() {
+        let fog = Fog::exponential(BLACK, 0.05);
+        assert_eq!(fog.amount(0.0), 0.0);
+        assert!(fog.amount(10.0) > 0.35);
+        // Every stretch further away hides less than the one before it
+        assert!(fog.amount(20.0) - fog.amount(10.0) < fog.amount(10.0));
+        assert!((fog.amount(fog.end()) - (1.0 - EXPONENTIAL_FOG_CUTOFF)).abs() < 1e-5);
+        // Thicker fog ends closer to the camera
+        assert!(Fog::exponential(BLACK, 0.1).end() < fog.end());
+    }
+}
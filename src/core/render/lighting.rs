@@ -0,0 +1,200 @@
+use crate::core::ecs::component::Transform;
+use crate::core::ecs::{Query, ECS};
+use crate::core::radians;
+use crate::core::render::model_matrix;
+use glam::{Vec2, Vec3};
+
+/// Lights the world with a single distant sun on top of an even ambient light. Every vertex is lit
+/// on its own and the light is blended across the faces in between, much like the original
+/// PlayStation did. Worlds without this resource are drawn fully lit, showing the textures as they
+/// are.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lighting {
+    /// The direction the sunlight travels in, pointing away from the sun.
+    pub sun_direction: Vec3,
+    pub sun_color: Vec3,
+    /// The light reaching every surface no matter which way it faces, keeping the sides facing
+    /// away from the sun from going completely dark.
+    pub ambient: Vec3,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vec3::new(-0.4, -1.0, -0.3).normalize(),
+            sun_color: Vec3::new(0.8, 0.75, 0.65),
+            ambient: Vec3::new(0.35, 0.38, 0.45),
+        }
+    }
+}
+
+/// The most lights lighting the world at once, which is as many as the shader has room for. Only
+/// the lights nearest to the camera are used when there are more of them.
+pub const MAX_LIGHTS: usize = 8;
+/// How much nearer to the camera a light has to be than the nearest light left out to shine at full
+/// strength. Lights about to be swapped for another fade out rather than popping out of existence.
+const LIGHT_FADE_DISTANCE: f32 = 8.0;
+
+/// Shines in every direction from the entity, such as a street lamp. The light fades out with the
+/// distance, reaching nothing beyond its range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PointLight {
+    pub color: Vec3,
+    pub range: f32,
+    /// Where the light sits, in the space of the model of the entity.
+    pub offset: Vec3,
+}
+
+/// Shines in a cone from the entity, such as the headlights of a car. The light fades out with the
+/// distance as well as towards the edge of the cone, reaching nothing beyond its range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpotLight {
+    pub color: Vec3,
+    pub range: f32,
+    /// Where the light sits, in the space of the model of the entity.
+    pub offset: Vec3,
+    /// The direction the light shines in, in the space of the model of the entity.
+    pub direction: Vec3,
+    /// The angle between the direction and the edge of the cone, in degrees. The light is at full
+    /// strength up to the inner angle and fades out towards the outer angle.
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+/// A light placed in the world, as handed to the shader.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightSource {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub range: f32,
+    /// The direction of a spot light, or zero for a point light.
+    pub direction: Vec3,
+    /// The cosines of the inner and outer angle of a spot light. Point lights have a cone wide
+    /// enough to light everything around them.
+    pub cone: Vec2,
+}
+
+/// Collects the lights nearest to `eye`, at most `MAX_LIGHTS` of them, nearest first.
+pub fn nearest_lights(ecs: &ECS, eye: Vec3) -> Vec<LightSource> {
+    let mut lights = vec![];
+    for id in ecs.query(
+        &Query::new()
+            .with::<Transform>()
+            .with::<PointLight>()
+            .build(),
+    ) {
+        let matrix = model_matrix(&ecs.clone_component::<Transform>(id).unwrap());
+        let light = ecs.clone_component::<PointLight>(id).unwrap();
+        lights.push(LightSource {
+            position: matrix.transform_point3(light.offset),
+            color: light.color,
+            range: light.range,
+            direction: Vec3::ZERO,
+            cone: Vec2::new(-2.0, -3.0),
+        });
+    }
+    for id in ecs.query(&Query::new().with::<Transform>().with::<SpotLight>().build()) {
+        let matrix = model_matrix(&ecs.clone_component::<Transform>(id).unwrap());
+        let light = ecs.clone_component::<SpotLight>(id).unwrap();
+        lights.push(LightSource {
+            position: matrix.transform_point3(light.offset),
+            color: light.color,
+            range: light.range,
+            direction: matrix
+                .transform_vector3(light.direction)
+                .normalize_or_zero(),
+            cone: Vec2::new(
+                radians(light.inner_angle).cos(),
+                radians(light.outer_angle).cos(),
+            ),
+        });
+    }
+    // Sorting is stable, so lights equally far away always end up in the same order
+    lights.sort_by(|a, b| {
+        a.position
+            .distance(eye)
+            .total_cmp(&b.position.distance(eye))
+    });
+    if let Some(cutoff) = lights
+        .get(MAX_LIGHTS)
+        .map(|light| light.position.distance(eye))
+    {
+        lights.truncate(MAX_LIGHTS);
+        for light in lights.iter_mut() {
+            let fade = (cutoff - light.position.distance(eye)) / LIGHT_FADE_DISTANCE;
+            light.color *= fade.clamp(0.0, 1.0);
+        }
+    }
+    lights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::{ECSBuilder, EntityID};
+
+    fn world() -> ECS {
+        ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<PointLight>()
+            .with_component::<SpotLight>()
+            .build()
+    }
+
+    fn spawn(ecs: &mut ECS, position: Vec3, rotation: f32) -> EntityID {
+        let id = ecs.create_entity();
+        let transform = Transform {
+            position,
+            rotation: Vec3::new(0.0, rotation, 0.0),
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(id, transform).unwrap();
+        id
+    }
+
+    fn lamp(ecs: &mut ECS, x: f32) {
+        let id = spawn(ecs, Vec3::new(x, 0.0, 0.0), 0.0);
+        let light = PointLight {
+            color: Vec3::ONE,
+            range: 10.0,
+            offset: Vec3::new(0.0, 5.0, 0.0),
+        };
+        ecs.attach_component(id, light).unwrap();
+    }
+
+    #[test]
+    fn only_the_nearest_lights_are_used() {
+        let mut ecs = world();
+        for x in [
+            100.0, 90.0, 74.0, 60.0, 50.0, 70.0, 40.0, 30.0, 20.0, 10.0, 0.0,
+        ] {
+            lamp(&mut ecs, x);
+        }
+        let lights = nearest_lights(&ecs, Vec3::new(0.0, 5.0, 0.0));
+        assert_eq!(lights.len(), MAX_LIGHTS);
+        assert_eq!(lights[0].position, Vec3::new(0.0, 5.0, 0.0));
+        assert_eq!(lights[7].position, Vec3::new(70.0, 5.0, 0.0));
+        // The furthest light is about to be swapped for the one at 74, and has faded out halfway
+        assert_eq!(lights[6].color, Vec3::ONE);
+        assert!(lights[7].color.abs_diff_eq(Vec3::splat(0.5), 1e-5));
+    }
+
+    #[test]
+    fn spot_light_turns_with_its_entity() {
+        let mut ecs = world();
+        let car = spawn(&mut ecs, Vec3::new(1.0, 0.0, 1.0), 90.0);
+        let headlights = SpotLight {
+            color: Vec3::ONE,
+            range: 20.0,
+            offset: Vec3::new(0.0, 0.5, 1.0),
+            direction: Vec3::Z,
+            inner_angle: 60.0,
+            outer_angle: 90.0,
+        };
+        ecs.attach_component(car, headlights).unwrap();
+        let light = nearest_lights(&ecs, Vec3::ZERO)[0];
+        assert!(light.position.abs_diff_eq(Vec3::new(2.0, 0.5, 1.0), 1e-5));
+        assert!(light.direction.abs_diff_eq(Vec3::X, 1e-5));
+        assert!(light.cone.abs_diff_eq(Vec2::new(0.5, 0.0), 1e-5));
+    }
+}
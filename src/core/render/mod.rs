@@ -1,15 +1,37 @@
-use crate::core::ecs::component::{CameraTarget, Lens, Model, Transform};
+use crate::core::ecs::component::{CameraTarget, Lens, Model, Transform, INSTANCE_MATRIX_LOCATION};
 use crate::core::ecs::{Query, ECS};
 use crate::core::physics::{DynamicPhysicsBody, PhysicsBody};
+use crate::core::render::culling::{BoundingSphere, DrawDistance, Frustum, RenderStats};
+use crate::core::render::fog::{Fog, FogFalloff};
+use crate::core::render::lighting::{LightSource, Lighting};
+use crate::core::render::psx::PsxMode;
 use crate::core::render::shader::Shader;
+use crate::core::render::shadow::{ShadowMode, Shadows};
 use crate::core::{radians, Mouse};
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
+use std::collections::BTreeMap;
 
+pub mod culling;
+pub mod fog;
+pub mod lighting;
 pub mod model;
+pub mod psx;
 pub mod shader;
+pub mod shadow;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Color(pub f32, pub f32, pub f32, pub f32);
 
+/// The colour the screen is cleared with when there is no fog to match.
+pub const DEFAULT_CLEAR_COLOR: Color = Color(0.0, 0.05, 0.05, 1.0);
+
+/// The colour to clear the screen with, which is the colour of the fog so that the far end of the
+/// world fades seamlessly into the background.
+pub fn clear_color(ecs: &ECS) -> Color {
+    ecs.get_resource::<Fog>()
+        .map_or(DEFAULT_CLEAR_COLOR, |fog| fog.color)
+}
+
 pub fn clear(color: &Color) {
     unsafe {
         gl::ClearColor(color.0, color.1, color.2, color.3);
@@ -51,27 +73,132 @@ pub fn draw(ecs: &mut ECS) {
             .unwrap()
             .0,
     );
-    let models = ecs.query(&Query::new().with::<Transform>().with::<Model>().build());
+    let frustum = Frustum::new(&projection_matrix, &view_matrix);
+    let eye = view_matrix.inverse().w_axis.truncate();
+    // Nothing beyond the end of the fog can be seen, so it is culled along with whatever is
+    // beyond the draw distance
+    let fog_end = ecs.get_resource::<Fog>().map(Fog::end);
+    let draw_distance = [
+        ecs.get_resource::<DrawDistance>()
+            .map(|distance| distance.0),
+        fog_end,
+    ]
+    .into_iter()
+    .flatten()
+    .reduce(f32::min);
+    let (batches, stats) = batch_models(ecs, |sphere| {
+        culling::is_visible(&frustum, eye, draw_distance, sphere)
+    });
+    if let Some(render_stats) = ecs.get_resource_mut::<RenderStats>() {
+        *render_stats = stats;
+    }
     unsafe {
         gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        gl::UseProgram(shader_id);
+        shader::set_mat4(shader_id, "projection", &projection_matrix);
+        shader::set_mat4(shader_id, "view", &view_matrix);
+        set_psx_uniforms(ecs, shader_id);
+        set_lighting_uniforms(ecs, shader_id, eye);
+        set_fog_uniforms(ecs, shader_id);
+        set_shadow_uniforms(ecs, shader_id, eye);
+        draw_batches(batches);
     }
-    for id in models {
+}
+
+/// Renders the shadow map from the sun, covering the area around the camera target, and then
+/// points rendering back at the window of `window_size`. Does nothing unless shadows are mapped.
+pub fn draw_shadows(ecs: &mut ECS, window_size: (i32, i32)) {
+    let Some(sun_direction) = ecs
+        .get_resource::<Lighting>()
+        .map(|lighting| lighting.sun_direction)
+    else {
+        return;
+    };
+    let Some(shadows) = ecs.get_resource::<Shadows>() else {
+        return;
+    };
+    let ShadowMode::Mapped { resolution, .. } = shadows.mode else {
+        return;
+    };
+    let extent = shadows.extent;
+    let target_id = ecs.query(
+        &Query::new()
+            .with::<CameraTarget>()
+            .with::<Transform>()
+            .build(),
+    )[0];
+    let center = ecs
+        .clone_component::<Transform>(target_id)
+        .unwrap()
+        .position;
+    let light_space = shadow::light_space_matrix(sun_direction, center, extent, resolution);
+    // Anything casting a shadow into the map is within the box the map covers
+    let frustum = Frustum::new(&light_space, &Mat4::IDENTITY);
+    let (batches, _) = batch_models(ecs, |sphere| frustum.contains(sphere));
+
+    let shadows = ecs.get_resource_mut::<Shadows>().unwrap();
+    shadows.begin_pass(resolution, light_space);
+    let shader_id = shadows.shader().get_id();
+    unsafe {
+        gl::UseProgram(shader_id);
+        shader::set_mat4(shader_id, "light_space", &light_space);
+        draw_batches(batches);
+    }
+    ecs.get_resource::<Shadows>().unwrap().end_pass(window_size);
+}
+
+/// Batches the models passing `is_visible` by the model they share, keyed by the vertex array of
+/// the model so that the batches are always drawn in the same order. Every batch holds the model
+/// matrices of its instances, one after another.
+fn batch_models(
+    ecs: &ECS,
+    is_visible: impl Fn(&BoundingSphere) -> bool,
+) -> (BTreeMap<u32, (Model, Vec<f32>)>, RenderStats) {
+    let mut batches: BTreeMap<u32, (Model, Vec<f32>)> = BTreeMap::new();
+    let mut stats = RenderStats::default();
+    for id in ecs.query(&Query::new().with::<Transform>().with::<Model>().build()) {
         let model = ecs.clone_component::<Model>(id).unwrap();
         let model_matrix = model_matrix(&ecs.clone_component::<Transform>(id).unwrap());
-        unsafe {
-            gl::UseProgram(shader_id);
-            shader::set_mat4(shader_id, "projection", &projection_matrix);
-            shader::set_mat4(shader_id, "view", &view_matrix);
-            shader::set_mat4(shader_id, "model", &model_matrix);
-            gl::BindVertexArray(model.vao);
-            for material in model.materials {
-                let texture_id = material.texture_id.or(Some(0)).unwrap();
-                gl::BindTexture(gl::TEXTURE_2D, texture_id);
-                gl::DrawArrays(gl::TRIANGLES, material.first_index, material.count);
-            }
-            gl::BindTexture(gl::TEXTURE_2D, 0);
-            gl::BindVertexArray(0);
+        let sphere = BoundingSphere::from_bounds(&model.bounds, &model_matrix);
+        if !is_visible(&sphere) {
+            stats.culled += 1;
+            continue;
         }
+        stats.drawn += 1;
+        batches
+            .entry(model.vao)
+            .or_insert_with(|| (model, vec![]))
+            .1
+            .extend(model_matrix.to_cols_array());
+    }
+    (batches, stats)
+}
+
+/// Draws every batch with the program in use, in a single instanced draw call per material.
+unsafe fn draw_batches(batches: BTreeMap<u32, (Model, Vec<f32>)>) {
+    for (model, matrices) in batches.into_values() {
+        let instances = (matrices.len() / 16) as i32;
+        gl::BindVertexArray(model.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, model.instance_vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            (size_of::<f32>() * matrices.len()) as isize,
+            matrices.as_ptr().cast(),
+            gl::STREAM_DRAW,
+        );
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        for material in model.materials {
+            let texture_id = material.texture_id.or(Some(0)).unwrap();
+            gl::BindTexture(gl::TEXTURE_2D, texture_id);
+            gl::DrawArraysInstanced(
+                gl::TRIANGLES,
+                material.first_index,
+                material.count,
+                instances,
+            );
+        }
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+        gl::BindVertexArray(0);
     }
 }
 
@@ -110,38 +237,19 @@ pub fn draw_debug(ecs: &mut ECS) {
             .0,
     );
 
+    let mut outlines = vec![];
     let ids = ecs.query(
         &Query::new()
             .with::<Transform>()
             .with::<DynamicPhysicsBody>()
             .build(),
     );
-    // The debug model must be available as a world resource if debug objects shall be drawn
-    let representation = ecs.get_resource::<Model>().unwrap();
-    unsafe {
-        gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
-    }
     for id in ids {
-        let model_matrix = physics_model_matrix(
+        outlines.extend(collider_outlines(
             &ecs.clone_component::<Transform>(id).unwrap(),
             &ecs.clone_component::<DynamicPhysicsBody>(id).unwrap().base,
-        );
-        unsafe {
-            gl::UseProgram(shader_id);
-            shader::set_mat4(shader_id, "projection", &projection_matrix);
-            shader::set_mat4(shader_id, "view", &view_matrix);
-            shader::set_mat4(shader_id, "model", &model_matrix);
-            gl::BindVertexArray(representation.vao);
-            for material in &representation.materials {
-                let texture_id = material.texture_id.or(Some(0)).unwrap();
-                gl::BindTexture(gl::TEXTURE_2D, texture_id);
-                gl::DrawArrays(gl::TRIANGLES, material.first_index, material.count);
-            }
-            gl::BindTexture(gl::TEXTURE_2D, 0);
-            gl::BindVertexArray(0);
-        }
+        ));
     }
-
     let ids = ecs.query(
         &Query::new()
             .with::<Transform>()
@@ -149,24 +257,228 @@ pub fn draw_debug(ecs: &mut ECS) {
             .build(),
     );
     for id in ids {
-        let model_matrix = physics_model_matrix(
+        outlines.extend(collider_outlines(
             &ecs.clone_component::<Transform>(id).unwrap(),
             &ecs.clone_component::<PhysicsBody>(id).unwrap(),
+        ));
+    }
+
+    // Every outline is drawn as a line loop of its own, all of them sharing a single buffer which
+    // is filled anew every frame.
+    let mut vertices: Vec<f32> = vec![];
+    let mut loops = vec![];
+    for outline in outlines {
+        loops.push(((vertices.len() / 5) as i32, outline.len() as i32));
+        for point in outline {
+            vertices.extend([point.x, point.y, point.z, 0.0, 0.0]);
+        }
+    }
+    if ecs.get_resource::<DebugLines>().is_none() {
+        ecs.register_resource(DebugLines::new());
+    }
+    let lines = ecs.get_resource::<DebugLines>().unwrap();
+    unsafe {
+        gl::BindVertexArray(lines.vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, lines.vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            (size_of::<f32>() * vertices.len()) as isize,
+            vertices.as_slice().as_ptr().cast(),
+            gl::STREAM_DRAW,
         );
+
+        // The outlines are already in world space. Without a buffer bound for the normal or the
+        // model matrix, both fall back to the current value of their attributes.
+        gl::VertexAttrib3f(2, 0.0, 1.0, 0.0);
+        for (column, value) in Mat4::IDENTITY.to_cols_array_2d().iter().enumerate() {
+            let [x, y, z, w] = *value;
+            gl::VertexAttrib4f(INSTANCE_MATRIX_LOCATION + column as u32, x, y, z, w);
+        }
+        gl::UseProgram(shader_id);
+        shader::set_mat4(shader_id, "projection", &projection_matrix);
+        shader::set_mat4(shader_id, "view", &view_matrix);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+        for (first, count) in loops {
+            gl::DrawArrays(gl::LINE_LOOP, first, count);
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+    }
+}
+
+/// The buffer the collider outlines are uploaded into on every debug draw. It is created on the
+/// first debug draw and kept as a world resource from then on, so only the upload repeats.
+struct DebugLines {
+    vao: u32,
+    vbo: u32,
+}
+
+impl DebugLines {
+    fn new() -> Self {
+        let (mut vao, mut vbo) = (0, 0);
         unsafe {
-            gl::UseProgram(shader_id);
-            shader::set_mat4(shader_id, "projection", &projection_matrix);
-            shader::set_mat4(shader_id, "view", &view_matrix);
-            shader::set_mat4(shader_id, "model", &model_matrix);
-            gl::BindVertexArray(representation.vao);
-            for material in &representation.materials {
-                let texture_id = material.texture_id.or(Some(0)).unwrap();
-                gl::BindTexture(gl::TEXTURE_2D, texture_id);
-                gl::DrawArrays(gl::TRIANGLES, material.first_index, material.count);
-            }
-            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::GenVertexArrays(1, &mut vao);
+            gl::BindVertexArray(vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::VertexAttribPointer(
+                0,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                (5 * size_of::<f32>()) as gl::types::GLint,
+                std::ptr::null(),
+            );
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                1,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                (5 * size_of::<f32>()) as gl::types::GLint,
+                (3 * size_of::<f32>()) as *const gl::types::GLvoid,
+            );
+            gl::EnableVertexAttribArray(1);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
             gl::BindVertexArray(0);
         }
+        Self { vao, vbo }
+    }
+}
+
+/// Outlines every polygon of the collider of a body twice, once around the bottom and once around
+/// the top of the box spanned by its transform.
+fn collider_outlines(transform: &Transform, body: &PhysicsBody) -> Vec<Vec<Vec3>> {
+    let half_height = transform.scale.y / 2.0;
+    body.collider
+        .polygons(transform)
+        .into_iter()
+        .flat_map(|polygon| {
+            [-half_height, half_height].map(|height| {
+                polygon
+                    .iter()
+                    .map(|point| Vec3::new(point.x, transform.position.y + height, point.y))
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+/// Sets up the shader for whichever parts of the PSX mode are turned on, turning all of them off
+/// when there is no PSX mode at all.
+unsafe fn set_psx_uniforms(ecs: &mut ECS, shader_id: u32) {
+    let Some(psx) = ecs.get_resource_mut::<PsxMode>() else {
+        shader::set_vec2(shader_id, "snap_resolution", Vec2::ZERO);
+        shader::set_int(shader_id, "affine", 0);
+        shader::set_int(shader_id, "color_depth", 0);
+        shader::set_int(shader_id, "dithering", 0);
+        gl::BindSampler(0, 0);
+        return;
+    };
+    let snapping = psx.vertex_snapping().map_or(Vec2::ZERO, Vec2::from);
+    shader::set_vec2(shader_id, "snap_resolution", snapping);
+    shader::set_int(shader_id, "affine", psx.affine_texture_mapping() as i32);
+    shader::set_int(
+        shader_id,
+        "color_depth",
+        psx.color_depth().unwrap_or(0) as i32,
+    );
+    shader::set_int(shader_id, "dithering", psx.dithering() as i32);
+    psx.bind_sampler();
+}
+
+/// Sets up the shader to light the world along with the lights nearest to `eye`, leaving it fully
+/// lit when there is no lighting at all.
+unsafe fn set_lighting_uniforms(ecs: &ECS, shader_id: u32, eye: Vec3) {
+    let (lighting, lights) = match ecs.get_resource::<Lighting>() {
+        Some(lighting) => (*lighting, lighting::nearest_lights(ecs, eye)),
+        None => (
+            Lighting {
+                sun_direction: Vec3::NEG_Y,
+                sun_color: Vec3::ZERO,
+                ambient: Vec3::ONE,
+            },
+            vec![],
+        ),
+    };
+    shader::set_vec3(
+        shader_id,
+        "sun_direction",
+        lighting.sun_direction.normalize_or_zero(),
+    );
+    shader::set_vec3(shader_id, "sun_color", lighting.sun_color);
+    shader::set_vec3(shader_id, "ambient", lighting.ambient);
+    shader::set_int(shader_id, "light_count", lights.len() as i32);
+    for (i, light) in lights.iter().enumerate() {
+        let LightSource {
+            position,
+            color,
+            range,
+            direction,
+            cone,
+        } = *light;
+        shader::set_vec3(shader_id, &format!("light_position[{i}]"), position);
+        shader::set_vec3(shader_id, &format!("light_color[{i}]"), color);
+        shader::set_f32(shader_id, &format!("light_range[{i}]"), range);
+        shader::set_vec3(shader_id, &format!("light_direction[{i}]"), direction);
+        shader::set_vec2(shader_id, &format!("light_cone[{i}]"), cone);
+    }
+}
+
+/// Sets up the shader to fade everything out into the fog, turning it off when there is no fog.
+unsafe fn set_fog_uniforms(ecs: &ECS, shader_id: u32) {
+    let Some(fog) = ecs.get_resource::<Fog>() else {
+        shader::set_int(shader_id, "fog_falloff", 0);
+        return;
+    };
+    let Color(r, g, b, _) = fog.color;
+    shader::set_vec3(shader_id, "fog_color", Vec3::new(r, g, b));
+    match fog.falloff {
+        FogFalloff::Linear { start, end } => {
+            shader::set_int(shader_id, "fog_falloff", 1);
+            shader::set_f32(shader_id, "fog_start", start);
+            shader::set_f32(shader_id, "fog_end", end);
+        }
+        FogFalloff::Exponential { density } => {
+            shader::set_int(shader_id, "fog_falloff", 2);
+            shader::set_f32(shader_id, "fog_density", density);
+        }
+    }
+}
+
+/// Sets up the shader to darken whatever is in the shadows, turning them off when there are no
+/// shadows or, for a shadow map, no sun to cast them.
+unsafe fn set_shadow_uniforms(ecs: &ECS, shader_id: u32, eye: Vec3) {
+    let Some(shadows) = ecs.get_resource::<Shadows>() else {
+        shader::set_int(shader_id, "shadow_mode", 0);
+        return;
+    };
+    match shadows.mode {
+        ShadowMode::Mapped { pcf_radius, .. } => {
+            // A shadow map left over from before the lighting was taken away has no sun to match
+            let depth_texture = shadows
+                .depth_texture()
+                .filter(|_| ecs.get_resource::<Lighting>().is_some());
+            let Some(depth_texture) = depth_texture else {
+                shader::set_int(shader_id, "shadow_mode", 0);
+                return;
+            };
+            shader::set_int(shader_id, "shadow_mode", 1);
+            shader::set_mat4(shader_id, "light_space", &shadows.light_space());
+            shader::set_int(shader_id, "shadow_pcf_radius", pcf_radius.max(0));
+            shader::set_int(shader_id, "shadow_map", 1);
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, depth_texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        ShadowMode::Blob => {
+            let blobs = shadow::blob_shadows(ecs, eye);
+            shader::set_int(shader_id, "shadow_mode", 2);
+            shader::set_int(shader_id, "blob_count", blobs.len() as i32);
+            for (i, blob) in blobs.iter().enumerate() {
+                shader::set_vec4(shader_id, &format!("blob_shadow[{i}]"), *blob);
+            }
+        }
     }
 }
 
@@ -198,13 +510,6 @@ pub fn model_matrix(transform: &Transform) -> Mat4 {
         * Mat4::from_rotation_z(radians(transform.rotation.z))
 }
 
-pub fn physics_model_matrix(transform: &Transform, body: &PhysicsBody) -> Mat4 {
-    Mat4::from_translation(transform.position)
-        * Mat4::from_scale(transform.scale)
-        * Mat4::from_rotation_y(radians(transform.rotation.y))
-        * Mat4::from_scale(Vec3::new(body.width, 1.0, body.depth))
-}
-
 pub fn build_camera(ecs: &mut ECS) {
     let id = ecs.create_entity();
     ecs.attach_component(
@@ -1,3 +1,4 @@
+use crate::core::ecs::component::Model;
 use glam::{Vec2, Vec3};
 use image::ImageReader;
 use std::collections::HashMap;
@@ -5,28 +6,50 @@ use std::fs::File;
 use std::io;
 use std::io::{BufRead, BufReader};
 
-pub struct MeshLoader(HashMap<String, Mesh>);
+/// Caches meshes by the path they were loaded from, along with the models they were uploaded to
+/// the GPU as. Every entity using the same asset shares a single model, which lets the renderer
+/// draw all of them at once.
+pub struct MeshLoader {
+    meshes: HashMap<String, Mesh>,
+    models: HashMap<String, Model>,
+}
 
 impl MeshLoader {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            meshes: HashMap::new(),
+            models: HashMap::new(),
+        }
     }
 
     pub fn load_obj_file(&mut self, file_path: &str) -> Result<Mesh, io::Error> {
         let key = String::from(file_path);
-        if self.0.contains_key(&key) {
-            return Ok(self.0.get(&key).unwrap().clone());
+        if self.meshes.contains_key(&key) {
+            return Ok(self.meshes.get(&key).unwrap().clone());
         }
         let mesh = parse_obj_file(file_path)?;
-        self.0.insert(key, mesh.clone());
+        self.meshes.insert(key, mesh.clone());
         Ok(mesh)
     }
+
+    /// Loads the mesh at `file_path` and uploads it to the GPU, unless it has been uploaded
+    /// already.
+    pub fn load_model(&mut self, file_path: &str) -> Result<Model, io::Error> {
+        if let Some(model) = self.models.get(file_path) {
+            return Ok(model.clone());
+        }
+        let model = Model::new(self.load_obj_file(file_path)?);
+        self.models.insert(String::from(file_path), model.clone());
+        Ok(model)
+    }
 }
 
 #[derive(Clone)]
 pub struct Mesh {
     pub materials: Vec<Material>,
     faces: Vec<Face>,
+    bounds: Bounds,
+    footprint: Vec<Vec2>,
 }
 
 impl Default for Mesh {
@@ -34,11 +57,56 @@ impl Default for Mesh {
         Self {
             materials: vec![],
             faces: vec![],
+            bounds: Bounds::default(),
+            footprint: vec![],
         }
     }
 }
 
+/// An axis-aligned box enclosing a mesh, in the space of the mesh itself.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    pub fn from_points(points: &[Vec3]) -> Self {
+        if points.is_empty() {
+            return Self::default();
+        }
+        points.iter().fold(
+            Self {
+                min: Vec3::INFINITY,
+                max: Vec3::NEG_INFINITY,
+            },
+            |bounds, point| Self {
+                min: bounds.min.min(*point),
+                max: bounds.max.max(*point),
+            },
+        )
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+}
+
 impl Mesh {
+    pub fn bounds(&self) -> &Bounds {
+        &self.bounds
+    }
+
+    /// The convex hull of the mesh as seen from above, with X and Z of every point stored as X and
+    /// Y. The points wind counter-clockwise around the hull.
+    pub fn footprint(&self) -> &[Vec2] {
+        &self.footprint
+    }
+
     pub fn serialize(&self) -> Vec<f32> {
         let mut buffer = vec![];
         for face in &self.faces {
@@ -48,6 +116,9 @@ impl Mesh {
                 buffer.push(vertex.position.z);
                 buffer.push(vertex.texture_coordinate.x);
                 buffer.push(vertex.texture_coordinate.y);
+                buffer.push(vertex.normal.x);
+                buffer.push(vertex.normal.y);
+                buffer.push(vertex.normal.z);
             }
         }
         buffer
@@ -64,6 +135,17 @@ type Face = [Vertex; 3];
 pub struct Vertex {
     position: Vec3,
     texture_coordinate: Vec2,
+    normal: Vec3,
+}
+
+/// A face whose normals are missing from the file, which are generated once the whole file has
+/// been read.
+struct Unshaded {
+    face: usize,
+    /// The index of the position of every corner, telling which corners are shared between faces.
+    positions: [usize; 3],
+    /// The smoothing group the face is in, where faces outside of any group are shaded flat.
+    smoothing_group: Option<u32>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -89,7 +171,10 @@ pub fn parse_obj_file(file_path: &str) -> Result<Mesh, io::Error> {
 
     let mut v: Vec<Vec3> = vec![];
     let mut vt: Vec<Vec2> = vec![];
+    let mut vn: Vec<Vec3> = vec![];
     let mut faces: Vec<Face> = vec![];
+    let mut unshaded: Vec<Unshaded> = vec![];
+    let mut smoothing_group: Option<u32> = None;
 
     let mut matlib: HashMap<String, Option<u32>> = HashMap::new();
     let mut materials: Vec<Material> = vec![];
@@ -116,21 +201,51 @@ pub fn parse_obj_file(file_path: &str) -> Result<Mesh, io::Error> {
                     1.0 - args.next().unwrap().parse::<f32>().unwrap(),
                 ));
             }
+            Some("vn") => {
+                vn.push(Vec3::new(
+                    args.next().unwrap().parse::<f32>().unwrap(),
+                    args.next().unwrap().parse::<f32>().unwrap(),
+                    args.next().unwrap().parse::<f32>().unwrap(),
+                ));
+            }
+            Some("s") => {
+                // Both `s off` and `s 0` take the faces that follow out of any smoothing group
+                smoothing_group = args
+                    .next()
+                    .and_then(|group| group.parse::<u32>().ok())
+                    .filter(|group| *group != 0);
+            }
             Some("f") => {
-                let parse_vertex = |raw: &str| -> Vertex {
-                    let mut iter = raw.split("/");
-                    let vertex = iter.next().unwrap().parse::<usize>().unwrap() - 1;
-                    let texture_coordinate = iter.next().unwrap().parse::<usize>().unwrap() - 1;
-                    Vertex {
-                        position: v.get(vertex).unwrap().clone(),
-                        texture_coordinate: vt.get(texture_coordinate).unwrap().clone(),
-                    }
+                // Vertices are written as `v`, `v/vt`, `v//vn` or `v/vt/vn`
+                let parse_vertex = |raw: &str| -> (Vertex, usize, bool) {
+                    let mut indices = raw
+                        .split("/")
+                        .map(|index| index.parse::<usize>().ok().map(|index| index - 1));
+                    let position = indices.next().flatten().unwrap();
+                    let texture_coordinate = indices.next().flatten();
+                    let normal = indices.next().flatten();
+                    let vertex = Vertex {
+                        position: *v.get(position).unwrap(),
+                        texture_coordinate: texture_coordinate
+                            .map_or(Vec2::ZERO, |index| *vt.get(index).unwrap()),
+                        normal: normal.map_or(Vec3::ZERO, |index| *vn.get(index).unwrap()),
+                    };
+                    (vertex, position, normal.is_some())
                 };
-                faces.push([
-                    parse_vertex(args.next().unwrap()),
-                    parse_vertex(args.next().unwrap()),
-                    parse_vertex(args.next().unwrap()),
-                ]);
+                let [a, b, c] = [
+                    args.next().unwrap(),
+                    args.next().unwrap(),
+                    args.next().unwrap(),
+                ]
+                .map(parse_vertex);
+                if !(a.2 && b.2 && c.2) {
+                    unshaded.push(Unshaded {
+                        face: faces.len(),
+                        positions: [a.1, b.1, c.1],
+                        smoothing_group,
+                    });
+                }
+                faces.push([a.0, b.0, c.0]);
                 vertex_index += 3;
                 materials.last_mut().unwrap().count += 3;
             }
@@ -145,7 +260,68 @@ pub fn parse_obj_file(file_path: &str) -> Result<Mesh, io::Error> {
             _ => {}
         }
     }
-    Ok(Mesh { faces, materials })
+    generate_normals(&mut faces, &unshaded);
+    Ok(Mesh {
+        faces,
+        materials,
+        bounds: Bounds::from_points(&v),
+        footprint: convex_hull(v.iter().map(|point| Vec2::new(point.x, point.z)).collect()),
+    })
+}
+
+/// Generates the normals missing from the file. Faces in a smoothing group share a normal at
+/// every corner with the other faces of the group meeting there, averaged by their area, which
+/// makes the group look like a single curved surface. Every other face is shaded flat, facing the
+/// side its corners wind counter-clockwise around.
+fn generate_normals(faces: &mut [Face], unshaded: &[Unshaded]) {
+    let face_normal = |face: &Face| {
+        (face[1].position - face[0].position).cross(face[2].position - face[0].position)
+    };
+    let mut smooth_normals: HashMap<(usize, u32), Vec3> = HashMap::new();
+    for face in unshaded {
+        if let Some(group) = face.smoothing_group {
+            let normal = face_normal(&faces[face.face]);
+            for position in face.positions {
+                *smooth_normals.entry((position, group)).or_default() += normal;
+            }
+        }
+    }
+    for face in unshaded {
+        let flat_normal = face_normal(&faces[face.face]).normalize_or_zero();
+        for (vertex, position) in faces[face.face].iter_mut().zip(face.positions) {
+            vertex.normal = face
+                .smoothing_group
+                .and_then(|group| smooth_normals[&(position, group)].try_normalize())
+                .unwrap_or(flat_normal);
+        }
+    }
+}
+
+/// Finds the smallest convex polygon enclosing all points using Andrew's monotone chain, which
+/// builds the lower and upper halves of the hull from the points sorted left to right.
+fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull: Vec<Vec2> = vec![];
+    for half in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in half {
+            // Points making a clockwise (or no) turn are inside of the hull
+            while let [.., a, b] = hull[start..] {
+                if (b - a).perp_dot(point - a) > 0.0 {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point of each half is the first point of the other one
+        hull.pop();
+    }
+    hull
 }
 
 fn parse_mtl_file(file_path: &str) -> Result<HashMap<String, Option<u32>>, io::Error> {
@@ -207,3 +383,92 @@ pub fn load_gl_texture(file_path: &str) -> u32 {
         texture_id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles folded along their shared edge like a roof, one facing up and to the left and
+    /// the other up and to the right.
+    fn roof(smoothing_group: Option<u32>) -> (Vec<Face>, Vec<Unshaded>) {
+        let vertex = |x: f32, y: f32, z: f32| Vertex {
+            position: Vec3::new(x, y, z),
+            ..Vertex::default()
+        };
+        let faces = vec![
+            [
+                vertex(-1.0, 0.0, 0.0),
+                vertex(0.0, 1.0, 1.0),
+                vertex(0.0, 1.0, 0.0),
+            ],
+            [
+                vertex(1.0, 0.0, 0.0),
+                vertex(0.0, 1.0, 0.0),
+                vertex(0.0, 1.0, 1.0),
+            ],
+        ];
+        let unshaded = vec![
+            Unshaded {
+                face: 0,
+                positions: [0, 1, 2],
+                smoothing_group,
+            },
+            Unshaded {
+                face: 1,
+                positions: [3, 2, 1],
+                smoothing_group,
+            },
+        ];
+        (faces, unshaded)
+    }
+
+    #[test]
+    fn flat_normals_face_away_from_each_face() {
+        let (mut faces, unshaded) = roof(None);
+        generate_normals(&mut faces, &unshaded);
+        let left = Vec3::new(-1.0, 1.0, 0.0).normalize();
+        let right = Vec3::new(1.0, 1.0, 0.0).normalize();
+        assert!(faces[0]
+            .iter()
+            .all(|vertex| vertex.normal.abs_diff_eq(left, 1e-5)));
+        assert!(faces[1]
+            .iter()
+            .all(|vertex| vertex.normal.abs_diff_eq(right, 1e-5)));
+    }
+
+    #[test]
+    fn smooth_normals_are_shared_along_the_ridge() {
+        let (mut faces, unshaded) = roof(Some(1));
+        generate_normals(&mut faces, &unshaded);
+        // The ridge is shared, so its corners point straight up, while the eaves are not
+        for vertex in faces.iter().flatten() {
+            let expected = if vertex.position.y == 1.0 {
+                Vec3::Y
+            } else {
+                Vec3::new(vertex.position.x, 1.0, 0.0).normalize()
+            };
+            assert!(vertex.normal.abs_diff_eq(expected, 1e-5));
+        }
+    }
+
+    #[test]
+    fn convex_hull_drops_inner_points() {
+        let hull = convex_hull(vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(2.0, 2.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 2.0),
+        ]);
+        assert_eq!(
+            hull,
+            vec![
+                Vec2::new(0.0, 0.0),
+                Vec2::new(2.0, 0.0),
+                Vec2::new(2.0, 2.0),
+                Vec2::new(0.0, 2.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+/// Mimics the look of the original PlayStation, each part of which can be toggled on its own. The
+/// defaults are what the console itself would do, though the mode starts out disabled until it is
+/// toggled on. Worlds without this resource render as usual.
+pub struct PsxMode {
+    /// Turns every part of the mode off at once, without losing how each part is set up.
+    pub enabled: bool,
+    /// The resolution to render at before scaling up to the window, without any smoothing.
+    pub resolution: Option<(i32, i32)>,
+    /// The size of the grid on screen which vertices are snapped to. The console lacked sub-pixel
+    /// precision, which made geometry wobble as it moved.
+    pub vertex_snapping: Option<(f32, f32)>,
+    /// Interpolates texture coordinates linearly across the screen rather than in perspective,
+    /// warping textures on surfaces seen at an angle.
+    pub affine_texture_mapping: bool,
+    /// How many bits are kept of every colour channel, 5 being the 15-bit colour of the console.
+    pub color_depth: Option<u32>,
+    /// Hides the banding of the reduced colour depth behind an ordered dither pattern.
+    pub dithering: bool,
+    /// Samples textures without any filtering or mipmapping, leaving every texel a sharp square.
+    pub nearest_filtering: bool,
+    framebuffer: Option<Framebuffer>,
+    sampler: Option<u32>,
+}
+
+impl Default for PsxMode {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            resolution: Some((320, 240)),
+            vertex_snapping: Some((160.0, 120.0)),
+            affine_texture_mapping: true,
+            color_depth: Some(5),
+            dithering: true,
+            nearest_filtering: true,
+            framebuffer: None,
+            sampler: None,
+        }
+    }
+}
+
+impl PsxMode {
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn resolution(&self) -> Option<(i32, i32)> {
+        self.resolution.filter(|_| self.enabled)
+    }
+
+    pub fn vertex_snapping(&self) -> Option<(f32, f32)> {
+        self.vertex_snapping.filter(|_| self.enabled)
+    }
+
+    pub fn affine_texture_mapping(&self) -> bool {
+        self.enabled && self.affine_texture_mapping
+    }
+
+    pub fn color_depth(&self) -> Option<u32> {
+        self.color_depth.filter(|_| self.enabled)
+    }
+
+    pub fn dithering(&self) -> bool {
+        self.enabled && self.dithering
+    }
+
+    pub fn nearest_filtering(&self) -> bool {
+        self.enabled && self.nearest_filtering
+    }
+
+    /// Points rendering at the low resolution framebuffer, creating it on first use or whenever
+    /// the resolution has changed. Renders straight to the window when the resolution is off.
+    pub fn begin_frame(&mut self, window_size: (i32, i32)) {
+        let Some(resolution) = self.resolution() else {
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                gl::Viewport(0, 0, window_size.0, window_size.1);
+            }
+            return;
+        };
+        if self
+            .framebuffer
+            .as_ref()
+            .is_none_or(|framebuffer| framebuffer.size != resolution)
+        {
+            if let Some(framebuffer) = self.framebuffer.take() {
+                framebuffer.delete();
+            }
+            self.framebuffer = Some(Framebuffer::new(resolution));
+        }
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer.as_ref().unwrap().id);
+            gl::Viewport(0, 0, resolution.0, resolution.1);
+        }
+    }
+
+    /// Scales the low resolution framebuffer up to fill the window, keeping every pixel sharp.
+    pub fn end_frame(&self, window_size: (i32, i32)) {
+        let (Some(resolution), Some(framebuffer)) = (self.resolution(), &self.framebuffer) else {
+            return;
+        };
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer.id);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+            gl::BlitFramebuffer(
+                0,
+                0,
+                resolution.0,
+                resolution.1,
+                0,
+                0,
+                window_size.0,
+                window_size.1,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, window_size.0, window_size.1);
+        }
+    }
+
+    /// Overrides the filtering of every texture drawn from texture unit 0 while nearest filtering
+    /// is on, leaving the textures themselves untouched.
+    pub fn bind_sampler(&mut self) {
+        if !self.nearest_filtering() {
+            unsafe { gl::BindSampler(0, 0) };
+            return;
+        }
+        let sampler = *self.sampler.get_or_insert_with(|| unsafe {
+            let mut sampler = 0;
+            gl::GenSamplers(1, &mut sampler);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::SamplerParameteri(sampler, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            sampler
+        });
+        unsafe { gl::BindSampler(0, sampler) };
+    }
+}
+
+/// An offscreen colour and depth buffer to render into.
+struct Framebuffer {
+    id: u32,
+    color: u32,
+    depth: u32,
+    size: (i32, i32),
+}
+
+impl Framebuffer {
+    fn new(size: (i32, i32)) -> Self {
+        let (mut id, mut color, mut depth) = (0, 0, 0);
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+
+            gl::GenTextures(1, &mut color);
+            gl::BindTexture(gl::TEXTURE_2D, color);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as i32,
+                size.0,
+                size.1,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                color,
+                0,
+            );
+
+            gl::GenRenderbuffers(1, &mut depth);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, size.0, size.1);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::RENDERBUFFER,
+                depth,
+            );
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        Self {
+            id,
+            color,
+            depth,
+            size,
+        }
+    }
+
+    fn delete(self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteTextures(1, &self.color);
+            gl::DeleteRenderbuffers(1, &self.depth);
+        }
+    }
+}
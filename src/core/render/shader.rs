@@ -1,4 +1,4 @@
-use glam::Mat4;
+use glam::{Mat4, Vec2, Vec3, Vec4};
 use std::ffi::CString;
 use std::{fs, io};
 
@@ -67,6 +67,48 @@ pub unsafe fn set_int(id: u32, name: &str, value: i32) {
     gl::Uniform1i(get_uniform_location(id, name), value)
 }
 
+/// Sets the float uniform `name` of the program `id`.
+///
+/// # Safety
+///
+/// A GL context must be current, and `id` must be a linked program which is currently in use.
+pub unsafe fn set_f32(id: u32, name: &str, value: f32) {
+    gl::Uniform1f(get_uniform_location(id, name), value)
+}
+
+/// Sets the two-component vector uniform `name` of the program `id`.
+///
+/// # Safety
+///
+/// A GL context must be current, and `id` must be a linked program which is currently in use.
+pub unsafe fn set_vec2(id: u32, name: &str, value: Vec2) {
+    gl::Uniform2f(get_uniform_location(id, name), value.x, value.y)
+}
+
+/// Sets the three-component vector uniform `name` of the program `id`.
+///
+/// # Safety
+///
+/// A GL context must be current, and `id` must be a linked program which is currently in use.
+pub unsafe fn set_vec3(id: u32, name: &str, value: Vec3) {
+    gl::Uniform3f(get_uniform_location(id, name), value.x, value.y, value.z)
+}
+
+/// Sets the four-component vector uniform `name` of the program `id`.
+///
+/// # Safety
+///
+/// A GL context must be current, and `id` must be a linked program which is currently in use.
+pub unsafe fn set_vec4(id: u32, name: &str, value: Vec4) {
+    gl::Uniform4f(
+        get_uniform_location(id, name),
+        value.x,
+        value.y,
+        value.z,
+        value.w,
+    )
+}
+
 pub unsafe fn get_uniform_location(id: u32, name: &str) -> i32 {
     let c_str = CString::new(name).unwrap();
     gl::GetUniformLocation(id, c_str.as_ptr())
@@ -0,0 +1,331 @@
+use crate::core::ecs::component::{Model, Transform};
+use crate::core::ecs::{Query, ECS};
+use crate::core::physics::DynamicPhysicsBody;
+use crate::core::render::model_matrix;
+use crate::core::render::shader::Shader;
+use glam::{Mat4, Vec3, Vec4};
+
+/// The most blob shadows drawn at once, which is as many as the shader has room for. Only the
+/// bodies nearest to the camera cast one when there are more of them.
+pub const MAX_BLOB_SHADOWS: usize = 8;
+/// How far in front of and behind the player the shadow map reaches along the sunlight, which has
+/// to be enough to fit the tallest building.
+const SHADOW_DEPTH: f32 = 50.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShadowMode {
+    /// Renders the world as seen from the sun into a map of `resolution` by `resolution` texels,
+    /// telling for every point drawn whether anything stands between it and the sun. The edges of
+    /// the shadows are softened by averaging over `pcf_radius` texels in every direction, or left
+    /// hard at zero.
+    Mapped { resolution: i32, pcf_radius: i32 },
+    /// Darkens a round blob on the ground under every dynamic body, which costs next to nothing
+    /// and suits machines too slow for a shadow map.
+    Blob,
+}
+
+/// Casts shadows onto the world. Shadow maps are cast from the sun of the lighting, which worlds
+/// without lighting have none of, while blob shadows need no sun at all. Worlds without this
+/// resource are drawn without any shadows.
+pub struct Shadows {
+    pub mode: ShadowMode,
+    /// How far the shadow map reaches from the player in every direction. Anything further away is
+    /// drawn without shadows.
+    pub extent: f32,
+    shader: Shader,
+    light_space: Mat4,
+    map: Option<ShadowMap>,
+}
+
+impl Shadows {
+    /// Casts shadows in `mode`, where `shader` renders the depth of the shadow map.
+    pub fn new(mode: ShadowMode, shader: Shader) -> Self {
+        Self {
+            mode,
+            extent: 24.0,
+            shader,
+            light_space: Mat4::IDENTITY,
+            map: None,
+        }
+    }
+
+    pub fn shader(&self) -> &Shader {
+        &self.shader
+    }
+
+    /// The matrix taking a point in the world into the shadow map, as of the latest shadow pass.
+    pub fn light_space(&self) -> Mat4 {
+        self.light_space
+    }
+
+    /// The depth texture of the shadow map, if the shadow map has been rendered at least once.
+    pub fn depth_texture(&self) -> Option<u32> {
+        self.map.as_ref().map(|map| map.depth)
+    }
+
+    /// Points rendering at the shadow map, creating it on first use or whenever the resolution has
+    /// changed, and clears it. Remembers `light_space` for the passes after it.
+    pub fn begin_pass(&mut self, resolution: i32, light_space: Mat4) {
+        if self
+            .map
+            .as_ref()
+            .is_none_or(|map| map.resolution != resolution)
+        {
+            if let Some(map) = self.map.take() {
+                map.delete();
+            }
+            self.map = Some(ShadowMap::new(resolution));
+        }
+        self.light_space = light_space;
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.map.as_ref().unwrap().id);
+            gl::Viewport(0, 0, resolution, resolution);
+            gl::Clear(gl::DEPTH_BUFFER_BIT);
+            // Pushes the depth of the shadow casters back a little, or surfaces facing the sun
+            // would shadow themselves in stripes
+            gl::Enable(gl::POLYGON_OFFSET_FILL);
+            gl::PolygonOffset(2.0, 4.0);
+        }
+    }
+
+    /// Points rendering back at the window, covering all of `window_size` again.
+    pub fn end_pass(&self, window_size: (i32, i32)) {
+        unsafe {
+            gl::Disable(gl::POLYGON_OFFSET_FILL);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, window_size.0, window_size.1);
+        }
+    }
+}
+
+/// The matrix taking a point in the world into the shadow map, covering `extent` units around
+/// `center` as seen from the sun. The centre is snapped to whole texels, which keeps the edges of
+/// the shadows from crawling as the player moves.
+pub fn light_space_matrix(sun_direction: Vec3, center: Vec3, extent: f32, resolution: i32) -> Mat4 {
+    let direction = sun_direction.normalize();
+    let up = if direction.cross(Vec3::Y).length_squared() < 1e-6 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let view = Mat4::look_at_rh(Vec3::ZERO, direction, up);
+    let texel = 2.0 * extent / resolution as f32;
+    let center = view.transform_point3(center);
+    let (x, y) = (
+        (center.x / texel).round() * texel,
+        (center.y / texel).round() * texel,
+    );
+    let projection = Mat4::orthographic_rh_gl(
+        x - extent,
+        x + extent,
+        y - extent,
+        y + extent,
+        -center.z - SHADOW_DEPTH,
+        -center.z + SHADOW_DEPTH,
+    );
+    projection * view
+}
+
+/// Collects a blob shadow for every dynamic body nearest to `eye`, at most `MAX_BLOB_SHADOWS` of
+/// them. Every blob is given as the centre of the bottom of the body along with the radius of the
+/// blob, wide enough to cover the body as seen from above.
+pub fn blob_shadows(ecs: &ECS, eye: Vec3) -> Vec<Vec4> {
+    let mut blobs: Vec<Vec4> = ecs
+        .query(
+            &Query::new()
+                .with::<Transform>()
+                .with::<Model>()
+                .with::<DynamicPhysicsBody>()
+                .build(),
+        )
+        .into_iter()
+        .map(|id| {
+            let transform = ecs.clone_component::<Transform>(id).unwrap();
+            let bounds = ecs.clone_component::<Model>(id).unwrap().bounds;
+            let center = bounds.center();
+            let bottom = model_matrix(&transform).transform_point3(Vec3::new(
+                center.x,
+                bounds.min.y,
+                center.z,
+            ));
+            let size = bounds.size() * transform.scale.abs();
+            bottom.extend(size.x.max(size.z) / 2.0)
+        })
+        .collect();
+    blobs.sort_by(|a, b| {
+        a.truncate()
+            .distance(eye)
+            .total_cmp(&b.truncate().distance(eye))
+    });
+    blobs.truncate(MAX_BLOB_SHADOWS);
+    blobs
+}
+
+/// An offscreen depth buffer, rendered from the sun.
+struct ShadowMap {
+    id: u32,
+    depth: u32,
+    resolution: i32,
+}
+
+impl ShadowMap {
+    fn new(resolution: i32) -> Self {
+        let (mut id, mut depth) = (0, 0);
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+
+            gl::GenTextures(1, &mut depth);
+            gl::BindTexture(gl::TEXTURE_2D, depth);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::DEPTH_COMPONENT24 as i32,
+                resolution,
+                resolution,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            // Everything outside of the map is as far away from the sun as can be, and thus lit
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_BORDER as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_BORDER as i32,
+            );
+            let border = [1.0f32; 4];
+            gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::TEXTURE_2D,
+                depth,
+                0,
+            );
+            // There is nothing but depth to render
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        Self {
+            id,
+            depth,
+            resolution,
+        }
+    }
+
+    fn delete(self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteTextures(1, &self.depth);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::ECSBuilder;
+    use crate::core::physics::collider::Collider;
+    use crate::core::physics::material::PhysicsMaterial;
+    use crate::core::physics::{PhysicsBody, ALL_LAYERS, DEFAULT_LAYER};
+    use crate::core::render::model::Bounds;
+    use glam::Vec2;
+
+    #[test]
+    fn shadow_map_is_centred_on_the_player() {
+        let sun = Vec3::new(-0.4, -1.0, -0.3);
+        let center = Vec3::new(37.3, 0.0, -12.9);
+        let matrix = light_space_matrix(sun, center, 24.0, 1024);
+        let projected = matrix.project_point3(center);
+        // Snapping moves the centre by at most half a texel
+        let half_texel = 1.0 / 1024.0;
+        assert!(projected.x.abs() <= half_texel + 1e-5);
+        assert!(projected.y.abs() <= half_texel + 1e-5);
+        assert!(projected.z.abs() < 1e-3);
+        // The roof of a building is nearer to the sun than the ground in its shadow
+        let roof = matrix.project_point3(center - sun.normalize() * 10.0);
+        assert!(roof.z < projected.z);
+        assert!(roof.truncate().abs_diff_eq(projected.truncate(), 1e-4));
+    }
+
+    #[test]
+    fn shadow_map_moves_in_whole_texels() {
+        let sun = Vec3::new(-0.4, -1.0, -0.3);
+        let a = light_space_matrix(sun, Vec3::new(10.0, 0.0, 10.0), 24.0, 256);
+        let b = light_space_matrix(sun, Vec3::new(10.3, 0.0, 10.0), 24.0, 256);
+        let shift = a.project_point3(Vec3::ZERO) - b.project_point3(Vec3::ZERO);
+        let texels = shift.truncate() * 128.0;
+        assert_ne!(texels, Vec2::ZERO);
+        assert!(texels.abs_diff_eq(texels.round(), 1e-3));
+    }
+
+    #[test]
+    fn blob_shadows_sit_under_the_nearest_bodies() {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<Model>()
+            .with_component::<DynamicPhysicsBody>()
+            .build();
+        let model = Model {
+            materials: vec![],
+            vao: 0,
+            instance_vbo: 0,
+            bounds: Bounds {
+                min: Vec3::new(-1.0, 0.0, -2.0),
+                max: Vec3::new(1.0, 1.5, 2.0),
+            },
+        };
+        // A row of cars driving away from the camera, with a building beside the first one
+        for i in 0..MAX_BLOB_SHADOWS + 2 {
+            let id = ecs.create_entity();
+            let transform = Transform {
+                position: Vec3::new(0.0, 1.0, i as f32 * 5.0),
+                rotation: Vec3::ZERO,
+                scale: Vec3::ONE,
+            };
+            ecs.attach_component(id, transform).unwrap();
+            ecs.attach_component(id, model.clone()).unwrap();
+            let body = DynamicPhysicsBody {
+                base: PhysicsBody {
+                    mass: 1.0,
+                    collider: Collider::rectangle(2.0, 4.0),
+                    layer: DEFAULT_LAYER,
+                    collides_with: ALL_LAYERS,
+                    material: PhysicsMaterial::default(),
+                    height: Some(1.5),
+                },
+                force: Vec3::ZERO,
+                velocity: Vec3::ZERO,
+                torque: 0.0,
+                angular_velocity: 0.0,
+                inertia: 1.0,
+                grounded: true,
+                rest_time: 0.0,
+            };
+            ecs.attach_component(id, body).unwrap();
+        }
+        let building = ecs.create_entity();
+        let transform = Transform {
+            position: Vec3::new(4.0, 0.0, 0.0),
+            rotation: Vec3::ZERO,
+            scale: Vec3::splat(3.0),
+        };
+        ecs.attach_component(building, transform).unwrap();
+        ecs.attach_component(building, model).unwrap();
+
+        let blobs = blob_shadows(&ecs, Vec3::new(0.0, 10.0, 60.0));
+        assert_eq!(blobs.len(), MAX_BLOB_SHADOWS);
+        assert_eq!(blobs[0], Vec4::new(0.0, 1.0, 45.0, 2.0));
+        assert_eq!(blobs[MAX_BLOB_SHADOWS - 1], Vec4::new(0.0, 1.0, 10.0, 2.0));
+    }
+}
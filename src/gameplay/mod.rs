@@ -1,11 +1,27 @@
-use crate::core::ecs::component::{CameraTarget, KeyboardControls, Model, Transform};
+use crate::core::ecs::component::{CameraTarget, KeyboardControls, Transform};
 use crate::core::ecs::{Query, ECS};
-use crate::core::physics::{DynamicPhysicsBody, PhysicsBody};
+use crate::core::physics::collider::Collider;
+use crate::core::physics::material::{PhysicsMaterial, SurfaceZone};
+use crate::core::physics::trigger::Trigger;
+use crate::core::physics::vehicle::{Vehicle, VehicleInput};
+use crate::core::physics::{
+    attach_static_body, box_inertia, DynamicPhysicsBody, PhysicsBody, ALL_LAYERS, DEFAULT_LAYER,
+};
+use crate::core::render::lighting::{PointLight, SpotLight};
 use crate::core::render::model::MeshLoader;
 use crate::core::Keymap;
 use glam::Vec3;
-use rand::{thread_rng, Rng};
-use std::collections::HashSet;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashSet, VecDeque};
+
+pub mod race;
+
+/// The physics layer of the player, letting triggers and other bodies single out the player.
+pub const PLAYER_LAYER: u32 = 1 << 1;
+
+/// The minimum thickness of the fence collider, in the space of the fence model.
+const FENCE_THICKNESS: f32 = 0.25;
 
 const OBSTACLE_MODEL_FILES: [&str; 3] = [
     "assets/models/building_07.obj",
@@ -15,7 +31,11 @@ const OBSTACLE_MODEL_FILES: [&str; 3] = [
 
 #[derive(Copy, Clone, Eq, Hash, PartialEq)]
 enum EntityType {
-    Obstacle, // Any object causing a road block such as a structure or road maintenance
+    Obstacle(usize), // Any object causing a road block, along with the index of its model
+    Goal,            // The tile which the player has to reach in order to finish the level
+    Grass,           // A patch of grass the player can drive over, but with less grip
+    OilSlick,        // A spill of oil leaving the player with next to no grip
+    Ice,             // A sheet of ice, not quite as slippery as oil but bouncier
 }
 
 pub struct Maze(Vec<Vec<HashSet<EntityType>>>);
@@ -65,6 +85,16 @@ impl Maze {
         neighbors
     }
 
+    /// The index of the model of the obstacle on the tile, into `OBSTACLE_MODEL_FILES`.
+    fn obstacle_model(&self, tile: (usize, usize)) -> Option<usize> {
+        self.0[tile.0][tile.1]
+            .iter()
+            .find_map(|entity_type| match entity_type {
+                EntityType::Obstacle(model) => Some(*model),
+                _ => None,
+            })
+    }
+
     fn empty(&self, tile: (usize, usize)) -> bool {
         self.0[tile.0][tile.1].is_empty()
     }
@@ -84,16 +114,71 @@ impl Maze {
     fn valid_tile(&self, tile: (usize, usize)) -> bool {
         tile.0 < self.0.len() && tile.1 < self.0[tile.0].len()
     }
+
+    /// Finds the empty tile with the longest path from `start`, walking only through empty tiles.
+    fn farthest_empty_tile(&self, start: (usize, usize)) -> (usize, usize) {
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        let mut farthest = start;
+        // A breadth-first search visits tiles in order of their path distance, which means that
+        // the last tile to be dequeued is guaranteed to be one of the farthest ones.
+        while let Some(tile) = queue.pop_front() {
+            farthest = tile;
+            for neighbor in self.neighbors(tile).into_iter().flatten() {
+                if self.empty(neighbor) && visited.insert(neighbor) {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        farthest
+    }
 }
 
-pub fn generate_cityscape(width: usize, height: usize) -> Maze {
+pub fn generate_cityscape(width: usize, height: usize, seed: u64) -> Maze {
     let mut maze = Maze::sized(width, height);
-    maze.fill(EntityType::Obstacle);
-    generate_step(&mut maze, (0, 0));
+    // The maze is carved out of obstacles, whose models are picked once the carving is done
+    maze.fill(EntityType::Obstacle(0));
+    let mut rng = StdRng::seed_from_u64(seed);
+    generate_step(&mut maze, (0, 0), &mut rng);
+    let goal = maze.farthest_empty_tile((0, 0));
+    maze.0[goal.0][goal.1].insert(EntityType::Goal);
+    scatter_surfaces(&mut maze, &mut rng);
+    pick_obstacle_models(&mut maze, &mut rng);
     maze
 }
 
-fn generate_step(maze: &mut Maze, tile: (usize, usize)) {
+/// Picks a model for every obstacle left standing, so that the look of the city varies by seed.
+fn pick_obstacle_models(maze: &mut Maze, rng: &mut StdRng) {
+    for i in 0..maze.0.len() {
+        for j in 0..maze.0[i].len() {
+            if maze.0[i][j].remove(&EntityType::Obstacle(0)) {
+                let model = rng.gen_range(0..OBSTACLE_MODEL_FILES.len());
+                maze.0[i][j].insert(EntityType::Obstacle(model));
+            }
+        }
+    }
+}
+
+/// Covers some of the empty tiles, other than the starting tile, with slippery surfaces.
+fn scatter_surfaces(maze: &mut Maze, rng: &mut StdRng) {
+    for i in 0..maze.0.len() {
+        for j in 0..maze.0[i].len() {
+            if (i, j) == (0, 0) || !maze.empty((i, j)) {
+                continue;
+            }
+            let roll = rng.gen::<f32>();
+            if roll < 0.05 {
+                maze.0[i][j].insert(EntityType::OilSlick);
+            } else if roll < 0.08 {
+                maze.0[i][j].insert(EntityType::Ice);
+            } else if roll < 0.18 {
+                maze.0[i][j].insert(EntityType::Grass);
+            }
+        }
+    }
+}
+
+fn generate_step(maze: &mut Maze, tile: (usize, usize), rng: &mut StdRng) {
     if maze.count_empty_neighbors(tile) >= 2 {
         return;
     }
@@ -113,8 +198,8 @@ fn generate_step(maze: &mut Maze, tile: (usize, usize)) {
         })
         .collect();
     while !neighbors.is_empty() {
-        let selection = thread_rng().gen_range(0..neighbors.len());
-        generate_step(maze, neighbors[selection]);
+        let selection = rng.gen_range(0..neighbors.len());
+        generate_step(maze, neighbors[selection], rng);
         neighbors.remove(selection);
     }
 }
@@ -122,8 +207,24 @@ fn generate_step(maze: &mut Maze, tile: (usize, usize)) {
 pub fn build_entities(ecs: &mut ECS, maze: &Maze) {
     for i in 0..maze.0.len() {
         for j in 0..maze.0[i].len() {
-            if maze.0[i][j].contains(&EntityType::Obstacle) {
-                spawn_obstacle_on_tile(ecs, (i as i32, j as i32));
+            if let Some(model) = maze.obstacle_model((i, j)) {
+                spawn_obstacle_on_tile(ecs, (i as i32, j as i32), model);
+            }
+            if maze.0[i][j].contains(&EntityType::Goal) {
+                race::spawn_goal_on_tile(ecs, (i as i32, j as i32));
+            }
+            if maze.0[i][j].contains(&EntityType::Grass) {
+                spawn_surface_on_tile(ecs, (i as i32, j as i32), PhysicsMaterial::grass());
+            }
+            if maze.0[i][j].contains(&EntityType::OilSlick) {
+                spawn_surface_on_tile(ecs, (i as i32, j as i32), PhysicsMaterial::oil());
+            }
+            if maze.0[i][j].contains(&EntityType::Ice) {
+                spawn_surface_on_tile(ecs, (i as i32, j as i32), PhysicsMaterial::ice());
+            }
+            // Every intersection is lit by a street lamp
+            if maze.empty((i, j)) && maze.count_empty_neighbors((i, j)) >= 3 {
+                spawn_lamp_on_tile(ecs, (i as i32, j as i32));
             }
         }
     }
@@ -145,18 +246,22 @@ pub fn build_entities(ecs: &mut ECS, maze: &Maze) {
     }
 }
 
-pub fn spawn_obstacle_on_tile(ecs: &mut ECS, tile: (i32, i32)) {
+/// Spawns the obstacle with the model at index `model` of `OBSTACLE_MODEL_FILES` on the tile.
+pub fn spawn_obstacle_on_tile(ecs: &mut ECS, tile: (i32, i32), model: usize) {
     let id = ecs.create_entity();
+    let file_path = OBSTACLE_MODEL_FILES[model];
     let mesh = ecs
         .get_resource_mut::<MeshLoader>()
         .unwrap()
-        .load_obj_file(
-            OBSTACLE_MODEL_FILES
-                .get(thread_rng().gen_range(0..OBSTACLE_MODEL_FILES.len()))
-                .unwrap(),
-        )
+        .load_obj_file(file_path)
         .unwrap();
-    ecs.attach_component(id, Model::new(mesh)).unwrap();
+    let model = ecs
+        .get_resource_mut::<MeshLoader>()
+        .unwrap()
+        .load_model(file_path)
+        .unwrap();
+    let collider = Collider::fit_hull(&mesh);
+    ecs.attach_component(id, model).unwrap();
     ecs.attach_component(
         id,
         Transform {
@@ -166,12 +271,16 @@ pub fn spawn_obstacle_on_tile(ecs: &mut ECS, tile: (i32, i32)) {
         },
     )
     .unwrap();
-    ecs.attach_component(
+    attach_static_body(
+        ecs,
         id,
         PhysicsBody {
             mass: 50.0,
-            width: 2.0,
-            depth: 2.0,
+            collider,
+            layer: DEFAULT_LAYER,
+            collides_with: ALL_LAYERS,
+            material: PhysicsMaterial::default(),
+            height: None,
         },
     )
     .unwrap()
@@ -184,7 +293,17 @@ pub fn spawn_fence_on_tile(ecs: &mut ECS, tile: (i32, i32), y_rot: f32) {
         .unwrap()
         .load_obj_file("assets/models/fence.obj")
         .unwrap();
-    ecs.attach_component(id, Model::new(mesh)).unwrap();
+    let model = ecs
+        .get_resource_mut::<MeshLoader>()
+        .unwrap()
+        .load_model("assets/models/fence.obj")
+        .unwrap();
+    // The fence is paper thin, which would let a car pushing against it squeeze through. Its
+    // collider is thickened on the side facing away from the maze instead.
+    let mut bounds = *mesh.bounds();
+    bounds.min.z = bounds.min.z.min(bounds.max.z - FENCE_THICKNESS);
+    let collider = Collider::from_bounds(&bounds);
+    ecs.attach_component(id, model).unwrap();
     ecs.attach_component(
         id,
         Transform {
@@ -194,12 +313,61 @@ pub fn spawn_fence_on_tile(ecs: &mut ECS, tile: (i32, i32), y_rot: f32) {
         },
     )
     .unwrap();
-    ecs.attach_component(
+    attach_static_body(
+        ecs,
         id,
         PhysicsBody {
             mass: 50.0,
-            width: 2.0,
-            depth: 2.0,
+            collider,
+            layer: DEFAULT_LAYER,
+            collides_with: ALL_LAYERS,
+            material: PhysicsMaterial::default(),
+            height: None,
+        },
+    )
+    .unwrap()
+}
+
+pub fn spawn_surface_on_tile(ecs: &mut ECS, tile: (i32, i32), material: PhysicsMaterial) {
+    let id = ecs.create_entity();
+    let model = ecs
+        .get_resource_mut::<MeshLoader>()
+        .unwrap()
+        .load_model("assets/models/unit_cube.obj")
+        .unwrap();
+    ecs.attach_component(id, model).unwrap();
+    ecs.attach_component(
+        id,
+        Transform {
+            scale: Vec3::new(6.0, 0.05, 6.0),
+            position: Vec3::new(8.0 * tile.0 as f32, 0.0, 8.0 * tile.1 as f32),
+            rotation: Vec3::default(),
+        },
+    )
+    .unwrap();
+    ecs.attach_component(id, Trigger::new(1.0, 1.0).with_mask(PLAYER_LAYER))
+        .unwrap();
+    ecs.attach_component(id, SurfaceZone { material }).unwrap()
+}
+
+/// Hangs a street lamp above the middle of the tile.
+pub fn spawn_lamp_on_tile(ecs: &mut ECS, tile: (i32, i32)) {
+    let id = ecs.create_entity();
+    ecs.attach_component(
+        id,
+        Transform {
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            position: Vec3::new(8.0 * tile.0 as f32, 6.0, 8.0 * tile.1 as f32),
+            rotation: Vec3::default(),
+        },
+    )
+    .unwrap();
+    ecs.attach_component(
+        id,
+        PointLight {
+            color: Vec3::new(1.0, 0.8, 0.5),
+            range: 12.0,
+            offset: Vec3::ZERO,
         },
     )
     .unwrap()
@@ -221,10 +389,33 @@ pub fn build_player(ecs: &mut ECS) {
         .unwrap()
         .load_obj_file("assets/models/player.obj")
         .unwrap();
-    ecs.attach_component(id, Model::new(mesh)).unwrap();
+    let model = ecs
+        .get_resource_mut::<MeshLoader>()
+        .unwrap()
+        .load_model("assets/models/player.obj")
+        .unwrap();
+    // Rounded ends let the car glance off corners rather than getting stuck on them
+    let collider = Collider::fit_capsule(&mesh);
+    let size = mesh.bounds().size();
+    let height = mesh.bounds().max.y;
+    ecs.attach_component(id, model).unwrap();
     ecs.attach_component(id, CameraTarget(12.0)).unwrap();
     ecs.attach_component(id, KeyboardControls::default())
         .unwrap();
+    ecs.attach_component(id, Vehicle::default()).unwrap();
+    // The headlights sit at the front of the car, angled slightly down towards the road
+    ecs.attach_component(
+        id,
+        SpotLight {
+            color: Vec3::new(1.0, 1.0, 0.9),
+            range: 24.0,
+            offset: Vec3::new(0.0, height * 0.5, mesh.bounds().max.z),
+            direction: Vec3::new(0.0, -0.15, 1.0),
+            inner_angle: 20.0,
+            outer_angle: 35.0,
+        },
+    )
+    .unwrap();
     ecs.attach_component(
         id,
         DynamicPhysicsBody {
@@ -232,55 +423,92 @@ pub fn build_player(ecs: &mut ECS) {
             velocity: Vec3::default(),
             base: PhysicsBody {
                 mass: 1.0,
-                width: 0.8,
-                depth: 2.0,
+                collider,
+                layer: PLAYER_LAYER,
+                collides_with: ALL_LAYERS,
+                material: PhysicsMaterial::default(),
+                height: Some(height),
             },
+            torque: 0.0,
+            angular_velocity: 0.0,
+            // The body is scaled by the transform, so the inertia has to be as well
+            inertia: box_inertia(1.0, size.x * 2.0, size.z * 2.0),
+            grounded: true,
+            rest_time: 0.0,
         },
     )
     .unwrap()
 }
 
-pub fn move_player(ecs: &mut ECS, delta_time: f32) {
-    let id = ecs.query(
-        &Query::new()
-            .with::<KeyboardControls>()
-            .with::<Transform>()
-            .with::<DynamicPhysicsBody>(),
-    )[0];
+pub fn move_player(ecs: &mut ECS) {
+    let id = ecs.query(&Query::new().with::<KeyboardControls>().with::<Vehicle>())[0];
     let controls = ecs.clone_component::<KeyboardControls>(id).unwrap();
-    let transform = ecs.clone_component::<Transform>(id).unwrap();
+    let keymap = ecs.get_resource::<Keymap>().unwrap();
+    let input = VehicleInput {
+        throttle: keymap.pressed(controls.forward) as i32 as f32,
+        brake: keymap.pressed(controls.backward) as i32 as f32,
+        steer: keymap.axis(controls.left, controls.right),
+        handbrake: keymap.pressed(controls.handbrake),
+    };
+    ecs.update_component::<Vehicle>(id, &mut |mut vehicle| {
+        vehicle.input = input;
+        vehicle
+    })
+    .unwrap()
+}
 
-    let drive_dir = ecs
-        .get_resource::<Keymap>()
-        .unwrap()
-        .axis(controls.forward, controls.backward);
-    ecs.update_component::<DynamicPhysicsBody>(id, &mut |mut body| {
-        if drive_dir == 0.0 {
-            body.force -= body.velocity;
-        } else {
-            body.force += transform.forward() * 120.0 * drive_dir * delta_time;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obstacle_models(maze: &Maze) -> Vec<Option<usize>> {
+        (0..maze.0.len())
+            .flat_map(|i| (0..maze.0[i].len()).map(move |j| (i, j)))
+            .map(|tile| maze.obstacle_model(tile))
+            .collect()
+    }
+
+    #[test]
+    fn farthest_tile_is_farthest_along_the_road() {
+        // A road winding around a block, where the far corner as the crow flies is only halfway
+        let mut maze = Maze::sized(3, 3);
+        maze.fill(EntityType::Obstacle(0));
+        for tile in [(0, 0), (1, 0), (2, 0), (2, 1), (2, 2), (1, 2), (0, 2)] {
+            maze.clear(tile);
         }
-        body
-    })
-    .unwrap();
-    let velocity = ecs
-        .get_component::<DynamicPhysicsBody>(id)
-        .unwrap()
-        .borrow()
-        .downcast_ref::<DynamicPhysicsBody>()
-        .unwrap()
-        .velocity
-        .clone();
-    let steer_dir = ecs
-        .get_resource::<Keymap>()
-        .unwrap()
-        .axis(controls.left, controls.right);
-    ecs.update_component::<Transform>(id, &mut |mut transform| {
-        if velocity.length().abs() < 0.025 {
-            return transform;
+        assert_eq!(maze.farthest_empty_tile((0, 0)), (0, 2));
+        assert_eq!(maze.farthest_empty_tile((2, 1)), (0, 2));
+    }
+
+    #[test]
+    fn obstacle_models_follow_the_seed() {
+        let first = obstacle_models(&generate_cityscape(10, 10, 7));
+        assert_eq!(first, obstacle_models(&generate_cityscape(10, 10, 7)));
+        // Every model shows up somewhere in a city of this size
+        for model in 0..OBSTACLE_MODEL_FILES.len() {
+            assert!(first.contains(&Some(model)));
         }
-        transform.rotation.y += 90.0 * delta_time * steer_dir;
-        transform
-    })
-    .unwrap()
+        assert!((0..8).any(|seed| obstacle_models(&generate_cityscape(10, 10, seed)) != first));
+    }
+
+    #[test]
+    fn surfaces_are_scattered_along_the_roads() {
+        const SURFACES: [EntityType; 3] =
+            [EntityType::Grass, EntityType::OilSlick, EntityType::Ice];
+        let maze = generate_cityscape(20, 20, 7);
+        for surface in SURFACES {
+            assert!(maze.0.iter().flatten().any(|tile| tile.contains(&surface)));
+        }
+        // Surfaces only ever cover roads, one at a time
+        for i in 0..maze.0.len() {
+            for j in 0..maze.0[i].len() {
+                let surfaces = SURFACES
+                    .iter()
+                    .filter(|surface| maze.0[i][j].contains(surface))
+                    .count();
+                assert!(surfaces <= 1);
+                assert!(surfaces == 0 || maze.obstacle_model((i, j)).is_none());
+            }
+        }
+    }
 }
@@ -0,0 +1,297 @@
+use crate::core::ecs::component::{KeyboardControls, Transform};
+use crate::core::ecs::{Query, ECS};
+use crate::core::physics::trigger::{Trigger, TriggerEvents};
+use crate::core::physics::DynamicPhysicsBody;
+use crate::core::render::model::MeshLoader;
+use crate::gameplay::PLAYER_LAYER;
+use glam::Vec3;
+use std::fs;
+use std::io;
+
+pub const BEST_TIMES_FILE: &str = "best_times.txt";
+
+/// Marks a trigger as the one which the player has to drive into in order to finish the level.
+#[derive(Copy, Clone)]
+pub struct Goal;
+
+pub enum RaceEvent {
+    Started,
+    Finished { time: f32, best: Option<f32> },
+}
+
+/// Events emitted by the race system since the last time they were drained.
+#[derive(Default)]
+pub struct RaceEvents(pub Vec<RaceEvent>);
+
+impl RaceEvents {
+    pub fn drain(&mut self) -> Vec<RaceEvent> {
+        self.0.drain(..).collect()
+    }
+}
+
+pub struct LapTimer {
+    pub seed: u64,
+    elapsed: Option<f32>,
+    finished: bool,
+}
+
+impl LapTimer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            elapsed: None,
+            finished: false,
+        }
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed.unwrap_or(0.0)
+    }
+
+    pub fn running(&self) -> bool {
+        self.elapsed.is_some() && !self.finished
+    }
+}
+
+pub fn spawn_goal_on_tile(ecs: &mut ECS, tile: (i32, i32)) {
+    let id = ecs.create_entity();
+    let model = ecs
+        .get_resource_mut::<MeshLoader>()
+        .unwrap()
+        .load_model("assets/models/unit_cube.obj")
+        .unwrap();
+    ecs.attach_component(id, model).unwrap();
+    ecs.attach_component(
+        id,
+        Transform {
+            scale: Vec3::new(4.0, 0.25, 4.0),
+            position: Vec3::new(8.0 * tile.0 as f32, 0.0, 8.0 * tile.1 as f32),
+            rotation: Vec3::default(),
+        },
+    )
+    .unwrap();
+    ecs.attach_component(id, Trigger::new(1.0, 1.0).with_mask(PLAYER_LAYER))
+        .unwrap();
+    ecs.attach_component(id, Goal).unwrap()
+}
+
+pub fn race_system(ecs: &mut ECS, delta_time: f32) {
+    let player = ecs.query(
+        &Query::new()
+            .with::<KeyboardControls>()
+            .with::<Transform>()
+            .with::<DynamicPhysicsBody>(),
+    )[0];
+    let body = ecs.clone_component::<DynamicPhysicsBody>(player).unwrap();
+
+    let timer = ecs.get_resource_mut::<LapTimer>().unwrap();
+    if timer.finished {
+        return;
+    }
+    let mut events = vec![];
+    match timer.elapsed.as_mut() {
+        Some(elapsed) => *elapsed += delta_time,
+        None if body.velocity.length() > 0.0 => {
+            // The timer starts the first time the player moves rather than when the level is
+            // spawned, that way the player gets to look around before committing to a route.
+            timer.elapsed = Some(0.0);
+            events.push(RaceEvent::Started);
+        }
+        None => return,
+    }
+    let time = timer.elapsed();
+    let seed = timer.seed;
+
+    let goals = ecs.query(&Query::new().with::<Goal>().with::<Trigger>().build());
+    for id in goals {
+        if ecs
+            .get_resource::<TriggerEvents>()
+            .unwrap()
+            .entered(id, player)
+        {
+            ecs.get_resource_mut::<LapTimer>().unwrap().finished = true;
+            let best = record_time(BEST_TIMES_FILE, seed, time);
+            events.push(RaceEvent::Finished { time, best });
+            break;
+        }
+    }
+    ecs.get_resource_mut::<RaceEvents>()
+        .unwrap()
+        .0
+        .append(&mut events);
+}
+
+/// Stores `time` as the best time for `seed` if it beats the previous one and returns the best
+/// time prior to this run. A broken best times file should never stop the player from finishing,
+/// hence any I/O errors are only reported.
+fn record_time(file_path: &str, seed: u64, time: f32) -> Option<f32> {
+    let best = load_best_time(file_path, seed).unwrap_or_else(|err| {
+        eprintln!("failed to load best times from {}: {}", file_path, err);
+        None
+    });
+    if best.is_none_or(|best| time < best) {
+        if let Err(err) = save_best_time(file_path, seed, time) {
+            eprintln!("failed to save best time to {}: {}", file_path, err);
+        }
+    }
+    best
+}
+
+/// Best times are stored as one `<seed> <seconds>` pair per line.
+pub fn load_best_time(file_path: &str, seed: u64) -> io::Result<Option<f32>> {
+    Ok(read_best_times(file_path)?
+        .into_iter()
+        .find(|(s, _)| *s == seed)
+        .map(|(_, time)| time))
+}
+
+pub fn save_best_time(file_path: &str, seed: u64, time: f32) -> io::Result<()> {
+    let mut times = read_best_times(file_path)?;
+    match times.iter_mut().find(|(s, _)| *s == seed) {
+        Some(entry) => entry.1 = time,
+        None => times.push((seed, time)),
+    }
+    let contents: String = times
+        .iter()
+        .map(|(seed, time)| format!("{} {}\n", seed, time))
+        .collect();
+    fs::write(file_path, contents)
+}
+
+fn read_best_times(file_path: &str) -> io::Result<Vec<(u64, f32)>> {
+    let contents = match fs::read_to_string(file_path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            let mut args = line.split(" ");
+            let seed = args.next()?.parse::<u64>().ok()?;
+            let time = args.next()?.parse::<f32>().ok()?;
+            Some((seed, time))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::{ECSBuilder, EntityID};
+    use crate::core::physics::collider::Collider;
+    use crate::core::physics::material::PhysicsMaterial;
+    use crate::core::physics::{box_inertia, PhysicsBody, ALL_LAYERS, DEFAULT_LAYER};
+
+    fn world() -> (ECS, EntityID) {
+        let mut ecs = ECSBuilder::new()
+            .with_component::<Transform>()
+            .with_component::<KeyboardControls>()
+            .with_component::<DynamicPhysicsBody>()
+            .with_component::<Goal>()
+            .with_resource(LapTimer::new(1))
+            .with_resource(RaceEvents::default())
+            .build();
+        let player = ecs.create_entity();
+        let transform = Transform {
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+        };
+        ecs.attach_component(player, transform).unwrap();
+        ecs.attach_component(player, KeyboardControls::default())
+            .unwrap();
+        let body = DynamicPhysicsBody {
+            base: PhysicsBody {
+                mass: 1.0,
+                collider: Collider::rectangle(1.0, 1.0),
+                layer: DEFAULT_LAYER,
+                collides_with: ALL_LAYERS,
+                material: PhysicsMaterial::default(),
+                height: None,
+            },
+            force: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            torque: 0.0,
+            angular_velocity: 0.0,
+            inertia: box_inertia(1.0, 1.0, 1.0),
+            grounded: true,
+            rest_time: 0.0,
+        };
+        ecs.attach_component(player, body).unwrap();
+        (ecs, player)
+    }
+
+    /// A best times file of its own for every test, removed before the test starts.
+    fn temp_file(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("xtra_cheez_{}_{}.txt", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn lap_timer_starts_when_the_player_moves() {
+        let (mut ecs, player) = world();
+        race_system(&mut ecs, 0.5);
+        assert!(!ecs.get_resource::<LapTimer>().unwrap().running());
+        assert!(ecs
+            .get_resource_mut::<RaceEvents>()
+            .unwrap()
+            .drain()
+            .is_empty());
+
+        ecs.update_component::<DynamicPhysicsBody>(player, &mut |mut body| {
+            body.velocity = Vec3::X;
+            body
+        })
+        .unwrap();
+        race_system(&mut ecs, 0.5);
+        race_system(&mut ecs, 0.25);
+        let timer = ecs.get_resource::<LapTimer>().unwrap();
+        assert!(timer.running());
+        // The frame the timer starts on is not counted
+        assert_eq!(timer.elapsed(), 0.25);
+        let events = ecs.get_resource_mut::<RaceEvents>().unwrap().drain();
+        assert!(matches!(events[..], [RaceEvent::Started]));
+    }
+
+    #[test]
+    fn best_times_round_trip() {
+        let path = temp_file("round_trip");
+        assert_eq!(load_best_time(&path, 1).unwrap(), None);
+        save_best_time(&path, 1, 12.5).unwrap();
+        save_best_time(&path, 2, 30.25).unwrap();
+        save_best_time(&path, 1, 11.75).unwrap();
+        assert_eq!(load_best_time(&path, 1).unwrap(), Some(11.75));
+        assert_eq!(load_best_time(&path, 2).unwrap(), Some(30.25));
+        assert_eq!(read_best_times(&path).unwrap().len(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn slower_time_keeps_the_best() {
+        let path = temp_file("slower_time");
+        assert_eq!(record_time(&path, 7, 20.0), None);
+        assert_eq!(record_time(&path, 7, 25.0), Some(20.0));
+        assert_eq!(load_best_time(&path, 7).unwrap(), Some(20.0));
+        assert_eq!(record_time(&path, 7, 18.0), Some(20.0));
+        assert_eq!(load_best_time(&path, 7).unwrap(), Some(18.0));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        let path = temp_file("malformed");
+        fs::write(&path, "3 14.5\ngarbage\n4\nfive 6.0\n6 fast\n\n8 9.5\n").unwrap();
+        assert_eq!(read_best_times(&path).unwrap(), vec![(3, 14.5), (8, 9.5)]);
+        assert_eq!(load_best_time(&path, 4).unwrap(), None);
+        // Saving keeps the readable times and drops the rest
+        save_best_time(&path, 4, 2.0).unwrap();
+        assert_eq!(
+            read_best_times(&path).unwrap(),
+            vec![(3, 14.5), (8, 9.5), (4, 2.0)]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
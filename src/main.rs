@@ -2,17 +2,45 @@ use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
 use sdl2::video::GLProfile;
 use std::collections::HashSet;
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 use xtra_cheez::core::ecs::component::{CameraTarget, KeyboardControls, Lens, Model, Transform};
 use xtra_cheez::core::ecs::ECSBuilder;
-use xtra_cheez::core::physics::{DynamicPhysicsBody, PhysicsBody};
+use xtra_cheez::core::physics::broadphase::Broadphase;
+use xtra_cheez::core::physics::event::CollisionEvents;
+use xtra_cheez::core::physics::gravity::{Gravity, Ground};
+use xtra_cheez::core::physics::joint::Joint;
+use xtra_cheez::core::physics::material::SurfaceZone;
+use xtra_cheez::core::physics::timestep::{self, FixedTimestep};
+use xtra_cheez::core::physics::trigger::{Trigger, TriggerEvents};
+use xtra_cheez::core::physics::vehicle::Vehicle;
+use xtra_cheez::core::physics::{DynamicPhysicsBody, KinematicBody, PhysicsBody};
+use xtra_cheez::core::render::culling::RenderStats;
+use xtra_cheez::core::render::fog::Fog;
+use xtra_cheez::core::render::lighting::{Lighting, PointLight, SpotLight};
 use xtra_cheez::core::render::model::MeshLoader;
+use xtra_cheez::core::render::psx::PsxMode;
 use xtra_cheez::core::render::shader::Shader;
+use xtra_cheez::core::render::shadow::{ShadowMode, Shadows};
 use xtra_cheez::core::render::Color;
 use xtra_cheez::core::{physics, render, Keymap, Mouse};
 use xtra_cheez::gameplay;
+use xtra_cheez::gameplay::race::{Goal, LapTimer, RaceEvent, RaceEvents};
+
+const WINDOW_SIZE: (i32, i32) = (800, 800);
 
 fn main() {
+    // A seed can be passed as the first argument in order to replay a previous level
+    let seed = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        });
+    println!("Generating level from seed {}", seed);
+
     let sdl_context = sdl2::init().unwrap();
 
     let video_subsystem = sdl_context.video().unwrap();
@@ -21,7 +49,7 @@ fn main() {
     gl_attr.set_context_version(3, 3);
 
     let window = video_subsystem
-        .window("XTRA CHEEZ", 800, 800)
+        .window("XTRA CHEEZ", WINDOW_SIZE.0 as u32, WINDOW_SIZE.1 as u32)
         .opengl()
         .build()
         .unwrap();
@@ -41,25 +69,50 @@ fn main() {
         .with_component::<KeyboardControls>()
         .with_component::<DynamicPhysicsBody>()
         .with_component::<PhysicsBody>()
+        .with_component::<KinematicBody>()
+        .with_component::<Joint>()
+        .with_component::<Vehicle>()
+        .with_component::<Trigger>()
+        .with_component::<Goal>()
+        .with_component::<SurfaceZone>()
+        .with_component::<Ground>()
+        .with_component::<PointLight>()
+        .with_component::<SpotLight>()
         .with_resource(Keymap(HashSet::new()))
         .with_resource(Mouse(0, 0))
+        .with_resource(Broadphase::new(8.0))
+        .with_resource(Gravity::default())
+        .with_resource(FixedTimestep::new(60))
+        .with_resource(TriggerEvents::default())
+        .with_resource(CollisionEvents::default())
+        .with_resource(LapTimer::new(seed))
+        .with_resource(RaceEvents::default())
         .with_resource(
             Shader::from_source_files("assets/shaders/vertex.glsl", "assets/shaders/fragment.glsl")
                 .unwrap(),
         )
         .with_resource(MeshLoader::new())
+        // The fog hides everything beyond 80 units, which is also where models stop being drawn
+        .with_resource(Fog::linear(Color(0.0, 0.05, 0.05, 1.0), 32.0, 80.0))
+        .with_resource(RenderStats::default())
+        .with_resource(PsxMode::default())
+        .with_resource(Lighting::default())
+        .with_resource(Shadows::new(
+            ShadowMode::Mapped {
+                resolution: 1024,
+                pcf_radius: 1,
+            },
+            Shader::from_source_files(
+                "assets/shaders/shadow_vertex.glsl",
+                "assets/shaders/shadow_fragment.glsl",
+            )
+            .unwrap(),
+        ))
         .build();
 
-    let unit_cube = ecs
-        .get_resource_mut::<MeshLoader>()
-        .unwrap()
-        .load_obj_file("assets/models/unit_cube.obj")
-        .unwrap();
-    ecs.register_resource(Model::new(unit_cube));
-
     render::build_camera(&mut ecs);
     gameplay::build_player(&mut ecs);
-    let maze = gameplay::generate_cityscape(10, 10);
+    let maze = gameplay::generate_cityscape(10, 10, seed);
     gameplay::build_entities(&mut ecs, &maze);
 
     let mut events = sdl_context.event_pump().unwrap();
@@ -79,23 +132,60 @@ fn main() {
             if quit(&event) {
                 break 'game;
             }
+            if toggle_psx_mode(&event) {
+                ecs.get_resource_mut::<PsxMode>().unwrap().toggle();
+            }
             ecs.get_resource_mut::<Keymap>().unwrap().consume(&event);
             ecs.get_resource_mut::<Mouse>().unwrap().consume(&event);
         }
 
         render::move_camera(&mut ecs, delta_time);
-        gameplay::move_player(&mut ecs, delta_time);
+        for step in timestep::steps(&mut ecs, delta_time) {
+            gameplay::move_player(&mut ecs);
+            physics::physics_system(&mut ecs, step);
+            gameplay::race::race_system(&mut ecs, step);
+            timestep::end_step(&mut ecs);
+        }
+        for event in ecs.get_resource_mut::<RaceEvents>().unwrap().drain() {
+            match event {
+                RaceEvent::Started => println!("Timer started"),
+                RaceEvent::Finished { time, best } => match best {
+                    Some(best) if best <= time => {
+                        println!("Finished in {:.2}s, best time is {:.2}s", time, best)
+                    }
+                    _ => println!("Finished in {:.2}s, a new best time!", time),
+                },
+            }
+        }
 
-        physics::collision_system(&mut ecs);
-        physics::velocity_system(&mut ecs, delta_time);
+        // Nothing reacts to collisions yet, they are only drained to keep them from piling up
+        ecs.get_resource_mut::<CollisionEvents>().unwrap().drain();
 
-        render::clear(&Color(0.0, 0.05, 0.05, 1.0));
+        render::draw_shadows(&mut ecs, WINDOW_SIZE);
+        ecs.get_resource_mut::<PsxMode>()
+            .unwrap()
+            .begin_frame(WINDOW_SIZE);
+        render::clear(&render::clear_color(&ecs));
         render::draw(&mut ecs);
         // render::draw_debug(&mut ecs);
+        ecs.get_resource::<PsxMode>()
+            .unwrap()
+            .end_frame(WINDOW_SIZE);
         window.gl_swap_window();
     }
 }
 
+fn toggle_psx_mode(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::KeyDown {
+            scancode: Some(Scancode::F1),
+            repeat: false,
+            ..
+        }
+    )
+}
+
 fn quit(event: &Event) -> bool {
     match event {
         Event::Quit { .. } => true,